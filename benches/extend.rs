@@ -0,0 +1,64 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use peapod::{Peapod, Phenotype};
+
+#[derive(Phenotype)]
+enum Event {
+    Click { x: u16, y: u16 },
+    Key(u8),
+    Idle,
+}
+
+const N: usize = 1_000_000;
+
+fn events() -> impl ExactSizeIterator<Item = Event> {
+    (0..N).map(|i| match i % 3 {
+        0 => Event::Click {
+            x: i as u16,
+            y: (i >> 16) as u16,
+        },
+        1 => Event::Key(i as u8),
+        _ => Event::Idle,
+    })
+}
+
+fn bench_extend(c: &mut Criterion) {
+    let mut group = c.benchmark_group("1M elements");
+
+    // What `Extend` used to do.
+    // Dropping a Peapod reknits every element, so keep that out of the timings
+    group.bench_function("push loop", |b| {
+        b.iter_with_large_drop(|| {
+            let mut pp = Peapod::with_capacity(N);
+            for event in events() {
+                pp.push(event);
+            }
+            black_box(pp)
+        })
+    });
+
+    group.bench_function("extend", |b| {
+        b.iter_with_large_drop(|| {
+            let mut pp = Peapod::new();
+            pp.extend(events());
+            black_box(pp)
+        })
+    });
+
+    group.bench_function("extend from filter", |b| {
+        // Filter hides the exact size, so the capacity has to grow as we go
+        b.iter_with_large_drop(|| {
+            let mut pp = Peapod::new();
+            pp.extend(events().filter(|_| true));
+            black_box(pp)
+        })
+    });
+
+    group.bench_function("from_trusted_len", |b| {
+        b.iter_with_large_drop(|| black_box(Peapod::from_trusted_len(events())))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_extend);
+criterion_main!(benches);
@@ -0,0 +1,72 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use peapod::{Peapod, Phenotype};
+
+const N: usize = 1_000_000;
+
+// Five variants need 3 bits, so packed tags straddle words
+macro_rules! op {
+    ($name:ident, $align:literal) => {
+        #[derive(Phenotype)]
+        #[phenotype(tag_align = $align)]
+        enum $name {
+            Push(u32),
+            Pop,
+            Add,
+            Jump(u16),
+            Halt,
+        }
+
+        impl $name {
+            fn pod() -> Peapod<Self> {
+                (0..N)
+                    .map(|i| match i % 5 {
+                        0 => Self::Push(i as u32),
+                        1 => Self::Pop,
+                        2 => Self::Add,
+                        3 => Self::Jump(i as u16),
+                        _ => Self::Halt,
+                    })
+                    .collect()
+            }
+        }
+    };
+}
+
+op!(Packed, "packed");
+op!(Nibble, "nibble");
+op!(Byte, "byte");
+
+fn bench_tag_align(c: &mut Criterion) {
+    let mut group = c.benchmark_group("1M tags");
+
+    let packed = Packed::pod();
+    let nibble = Nibble::pod();
+    let byte = Byte::pod();
+
+    group.bench_function("packed", |b| {
+        b.iter(|| {
+            (0..N)
+                .filter(|&i| packed.tag(black_box(i)) == Some(3))
+                .count()
+        })
+    });
+    group.bench_function("nibble", |b| {
+        b.iter(|| {
+            (0..N)
+                .filter(|&i| nibble.tag(black_box(i)) == Some(3))
+                .count()
+        })
+    });
+    group.bench_function("byte", |b| {
+        b.iter(|| {
+            (0..N)
+                .filter(|&i| byte.tag(black_box(i)) == Some(3))
+                .count()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_tag_align);
+criterion_main!(benches);
@@ -0,0 +1,65 @@
+//! Prints how some enums are laid out, and how much space a `Peapod` saves
+//! storing them.
+//!
+//! ```text
+//! cargo run --example peapod-inspect [-- [--json] [ELEMENTS]]
+//! ```
+//!
+//! To report on your own enums, derive `Phenotype` on them and add them to the
+//! registry in `main`, or build a `LayoutReport` the same way in a test or
+//! binary of your own.
+
+use peapod::{LayoutReport, Phenotype};
+use std::env;
+
+#[allow(dead_code)]
+#[derive(Phenotype)]
+enum Event {
+    Click { x: u16, y: u16 },
+    Key(char),
+    Scroll(f64),
+    Quit,
+}
+
+#[allow(dead_code)]
+#[derive(Phenotype)]
+enum Cell {
+    Dead,
+    Alive,
+}
+
+#[allow(dead_code)]
+#[derive(Phenotype)]
+enum Maybe<T> {
+    Just(T),
+    Nothing,
+}
+
+fn main() {
+    let mut json = false;
+    let mut elements = 1_000_000;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            n => {
+                elements = n
+                    .parse()
+                    .unwrap_or_else(|_| panic!("expected `--json` or a number, got {n}"))
+            }
+        }
+    }
+
+    // The registry: every enum to report on
+    let mut report = LayoutReport::new(elements);
+    report
+        .add::<Event>()
+        .add::<Cell>()
+        .add::<Maybe<u32>>()
+        .add::<Maybe<[u8; 3]>>();
+
+    if json {
+        println!("{}", report.to_json());
+    } else {
+        print!("{report}");
+    }
+}
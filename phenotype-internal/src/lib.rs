@@ -1,4 +1,22 @@
 // TODO: add examples
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet, BinaryHeap, LinkedList, VecDeque},
+    rc::{self, Rc},
+    string::String,
+    sync::{self, Arc},
+    vec::Vec,
+};
+use core::{
+    marker::PhantomData,
+    mem::{self, ManuallyDrop},
+    num::*,
+    ptr::NonNull,
+};
 
 /// This trait represents the behavior of an `enum`/tagged union.
 /// **Note**: it should only be implemented with `#[derive(Phenotype)]`
@@ -7,14 +25,52 @@
 /// it correctly. In particular, the `reknit` method can cause undefined behavior
 /// if called with invalid inputs. Manual implementation of the trait is heavily
 /// discouraged, but there may be cases (e.g. `const` generics) where it is necessary.
+/// Such impls have to fill in the per-variant tables `HAS_DATA`, `PAYLOAD_SIZES`
+/// and `VARIANTS` too, which the derive generates.
 pub unsafe trait Phenotype {
     /// The number of variants of the enum.
     const NUM_VARIANTS: usize;
 
     /// The number of bits needed to represent every variant of the enum.
     /// For example, if the enum has 4 variants, then two bits are needed.
+    /// Collections store each tag in exactly this many bits, so it can be
+    /// rounded up to align them, see `#[phenotype(tag_align = "...")]`.
+    /// Small fields can be packed into the tag too, see `VARIANT_BITS`.
     const BITS: usize;
 
+    /// The number of low bits of a tag that say which variant it is. The bits
+    /// above them hold any fields packed into the tag with
+    /// `#[phenotype(bits = N)]`. Without those, this can be left as `BITS`.
+    const VARIANT_BITS: usize = Self::BITS;
+
+    /// Whether each variant of the enum carries data, indexed by variant.
+    /// Unit variants (like `Option::None`) don't, so their part of `Value`
+    /// is never read. This slice is `NUM_VARIANTS` long.
+    const HAS_DATA: &'static [bool];
+
+    /// Whether no variant carries data, like a C-style enum, i.e. every entry
+    /// of `HAS_DATA` is `false`. Then `Value` holds nothing, and the enum can
+    /// be stored as nothing but its tags.
+    const ALL_UNIT: bool = {
+        let mut all_unit = true;
+        let mut i = 0;
+        while i < Self::HAS_DATA.len() {
+            all_unit &= !Self::HAS_DATA[i];
+            i += 1;
+        }
+        all_unit
+    };
+
+    /// The number of bytes each variant's data takes up, indexed by variant.
+    /// Unit variants, and fields packed into the tag, take up none. `Value` is as big as the largest of these.
+    /// This slice is `NUM_VARIANTS` long.
+    const PAYLOAD_SIZES: &'static [usize];
+
+    /// A description of each variant of the enum, indexed by variant: its name,
+    /// its fields and how much space they take up. This slice is
+    /// `NUM_VARIANTS` long.
+    const VARIANTS: &'static [VariantInfo];
+
     /// The number of bits `Phenotype` uses to represent and instance of a type.
     /// If the type `Phenotype` is being implemented for is generic,
     /// this will be `None`, as sizes may vary accross different
@@ -53,7 +109,7 @@ pub unsafe trait Phenotype {
     /// the state that the union is in.
     ///
     /// For example, consider the following example
-    /// ```
+    /// ```ignore
     /// #[derive(Phenotype)]
     /// enum UB {
     ///     U(usize), // -> tag = 0
@@ -76,6 +132,65 @@ pub unsafe trait Phenotype {
     /// }
     /// ```
     unsafe fn reknit(tag: usize, value: Self::Value) -> Self;
+
+    /// Returns which variant a tag is, its lowest `VARIANT_BITS` bits. This
+    /// is what indexes `HAS_DATA`, `PAYLOAD_SIZES` and `VARIANTS`.
+    #[inline]
+    fn variant(tag: usize) -> usize {
+        match 1usize.checked_shl(Self::VARIANT_BITS as u32) {
+            Some(bound) => tag & bound.wrapping_sub(1),
+            // Every bit says which variant it is
+            None => tag,
+        }
+    }
+}
+
+/// Describes one variant of an enum that implements `Phenotype`,
+/// see `Phenotype::VARIANTS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VariantInfo {
+    /// The name of the variant, like `Some`.
+    pub name: &'static str,
+    /// The tag `Phenotype` uses for the variant. With fields packed into the
+    /// tag, this is just its low `Phenotype::VARIANT_BITS` bits.
+    pub tag: usize,
+    /// The names of the variant's fields. Fields of tuple variants are
+    /// named by their index, like `0` and `1`.
+    pub field_names: &'static [&'static str],
+    /// The types of the variant's fields, as written in the enum definition.
+    /// Generic parameters are not substituted, so this might be `T`.
+    pub field_types: &'static [&'static str],
+    /// The number of bytes the variant's data takes up,
+    /// same as `Phenotype::PAYLOAD_SIZES`.
+    pub payload_size: usize,
+    /// The number of bits of the variant's fields packed into its tag with
+    /// `#[phenotype(bits = N)]`. These take up no bytes of `payload_size`.
+    pub inline_bits: usize,
+    /// The alignment the variant's fields need, the largest of their
+    /// alignments. It's 1 for unit variants. **Note**: with the default packed
+    /// layout, data isn't necessarily this aligned, see
+    /// `#[phenotype(layout = "...")]`.
+    pub align: usize,
+}
+
+/// Helpers for code generated by `#[derive(Phenotype)]`. Not public API.
+#[doc(hidden)]
+pub mod __private {
+    /// Picks between two types with a constant, see [`Choose`]
+    pub struct Select<const COND: bool>;
+
+    /// `Select<false>` chooses `IfFalse`, and `Select<true>` chooses `IfTrue`
+    pub trait Choose<IfFalse, IfTrue> {
+        type Out;
+    }
+
+    impl<IfFalse, IfTrue> Choose<IfFalse, IfTrue> for Select<false> {
+        type Out = IfFalse;
+    }
+
+    impl<IfFalse, IfTrue> Choose<IfFalse, IfTrue> for Select<true> {
+        type Out = IfTrue;
+    }
 }
 
 /// Some helpful methods for using `Phenotype`
@@ -93,3 +208,144 @@ pub trait PhenotypeDebug: Phenotype {
     /// was derived on the `Result` type.
     fn debug_tag(tag: usize) -> &'static str;
 }
+
+/// Marks types that hold no `UnsafeCell` of their own, only behind pointers,
+/// like the unstable `Freeze` in `core`.
+///
+/// Collections store elements `cleave`d, so a reference to an element points at
+/// a bitwise copy of it. Interior mutability through that reference would act on
+/// the copy, and could, say, `take` a `Box` out of a `Cell` that the collection
+/// still owns. So references are only handed out to `Frozen` elements.
+///
+/// `#[derive(Phenotype)]` implements it for enums whose fields are all `Frozen`,
+/// and `#[derive(Frozen)]` does the same for other types.
+/// # Safety
+/// A bitwise copy of the type must not share any `UnsafeCell` with the
+/// original: no `Cell`, `RefCell`, `Mutex`, atomics etc. stored inline. Anything
+/// behind a `Box`, `Vec`, `Rc`, reference or other pointer is fine, since the
+/// copy points at the same place.
+pub unsafe trait Frozen {}
+
+// These types are already as small as they get: rustc stores the `None` in a
+// niche, a bit pattern the `Some` can never have, so there's no tag to split
+// off. They're passed through whole, with no tag bits, so a `Peapod` of them
+// is as big as a `Vec`. Enums can do the same with
+// `#[phenotype(pass_through = "...")]`.
+macro_rules! pass_through {
+    ($(impl<$($lt:lifetime,)? $($param:ident)?> for $ty:ty;)*) => {
+        $(
+            unsafe impl<$($lt,)? $($param: ?Sized)?> Phenotype for $ty {
+                const NUM_VARIANTS: usize = 1;
+                const BITS: usize = 0;
+                const HAS_DATA: &'static [bool] = &[true];
+                const PAYLOAD_SIZES: &'static [usize] = &[mem::size_of::<Self>()];
+                const VARIANTS: &'static [VariantInfo] = &[VariantInfo {
+                    name: "Option",
+                    tag: 0,
+                    field_names: &[],
+                    field_types: &[],
+                    payload_size: mem::size_of::<Self>(),
+                    inline_bits: 0,
+                    align: mem::align_of::<Self>(),
+                }];
+                const PEAPOD_SIZE: Option<usize> = Some(mem::size_of::<Self>());
+                const IS_MORE_COMPACT: Option<bool> = Some(true);
+
+                type Value = ManuallyDrop<Self>;
+
+                fn cleave(self) -> (usize, Self::Value) {
+                    (0, ManuallyDrop::new(self))
+                }
+
+                unsafe fn reknit(_: usize, value: Self::Value) -> Self {
+                    ManuallyDrop::into_inner(value)
+                }
+            }
+
+            impl<$($lt,)? $($param: ?Sized)?> PhenotypeDebug for $ty {
+                fn discriminant(&self) -> usize {
+                    0
+                }
+
+                fn debug_tag(_: usize) -> &'static str {
+                    "Option"
+                }
+            }
+        )*
+    };
+}
+
+pass_through! {
+    impl<'a, T> for Option<&'a T>;
+    impl<'a, T> for Option<&'a mut T>;
+    impl<T> for Option<NonNull<T>>;
+    impl<T> for Option<Box<T>>;
+    impl<> for Option<NonZeroU8>;
+    impl<> for Option<NonZeroU16>;
+    impl<> for Option<NonZeroU32>;
+    impl<> for Option<NonZeroU64>;
+    impl<> for Option<NonZeroU128>;
+    impl<> for Option<NonZeroUsize>;
+    impl<> for Option<NonZeroI8>;
+    impl<> for Option<NonZeroI16>;
+    impl<> for Option<NonZeroI32>;
+    impl<> for Option<NonZeroI64>;
+    impl<> for Option<NonZeroI128>;
+    impl<> for Option<NonZeroIsize>;
+}
+
+// These own or borrow whatever they hold through a pointer, if at all
+macro_rules! frozen {
+    ($($ty:ty),* $(,)?) => {
+        $(unsafe impl Frozen for $ty {})*
+    };
+}
+
+frozen! {
+    (), bool, char, str, String, u8, u16, u32, u64, u128, usize, i8, i16, i32,
+    i64, i128, isize, f32, f64, NonZeroU8, NonZeroU16, NonZeroU32, NonZeroU64,
+    NonZeroU128, NonZeroUsize, NonZeroI8, NonZeroI16, NonZeroI32, NonZeroI64,
+    NonZeroI128, NonZeroIsize, core::time::Duration, core::cmp::Ordering,
+}
+
+unsafe impl<T: ?Sized> Frozen for &T {}
+unsafe impl<T: ?Sized> Frozen for &mut T {}
+unsafe impl<T: ?Sized> Frozen for *const T {}
+unsafe impl<T: ?Sized> Frozen for *mut T {}
+unsafe impl<T: ?Sized> Frozen for NonNull<T> {}
+unsafe impl<T: ?Sized> Frozen for PhantomData<T> {}
+unsafe impl<T: ?Sized> Frozen for Box<T> {}
+unsafe impl<T: ?Sized> Frozen for Rc<T> {}
+unsafe impl<T: ?Sized> Frozen for Arc<T> {}
+unsafe impl<T: ?Sized> Frozen for rc::Weak<T> {}
+unsafe impl<T: ?Sized> Frozen for sync::Weak<T> {}
+unsafe impl<T> Frozen for Vec<T> {}
+unsafe impl<T> Frozen for VecDeque<T> {}
+unsafe impl<T> Frozen for LinkedList<T> {}
+unsafe impl<T> Frozen for BinaryHeap<T> {}
+unsafe impl<T> Frozen for BTreeSet<T> {}
+unsafe impl<K, V> Frozen for BTreeMap<K, V> {}
+
+// These hold their contents inline
+unsafe impl<T: Frozen> Frozen for ManuallyDrop<T> {}
+unsafe impl<T: Frozen> Frozen for Option<T> {}
+unsafe impl<T: Frozen, E: Frozen> Frozen for Result<T, E> {}
+unsafe impl<T: Frozen> Frozen for [T] {}
+unsafe impl<T: Frozen, const N: usize> Frozen for [T; N] {}
+
+macro_rules! frozen_tuples {
+    ($(($($param:ident),+))*) => {
+        $(unsafe impl<$($param: Frozen),+> Frozen for ($($param,)+) {})*
+    };
+}
+
+frozen_tuples! {
+    (A)
+    (A, B)
+    (A, B, C)
+    (A, B, C, D)
+    (A, B, C, D, E)
+    (A, B, C, D, E, F)
+    (A, B, C, D, E, F, G)
+    (A, B, C, D, E, F, G, H)
+}
@@ -0,0 +1,189 @@
+use proc_macro_error::abort;
+use syn::{Attribute, Field, Lit, Meta, NestedMeta, Type};
+
+const NOTE: &str = r#"expected `tag_align = "packed" | "nibble" | "byte"`, `layout = "packed" | "aligned" | "auto"` or `pass_through = "never" | "auto" | "always"`"#;
+
+/// How the tags of an enum are laid out, from `#[phenotype(tag_align = "...")]`
+#[derive(Clone, Copy, Default)]
+pub enum TagAlign {
+    /// Tags take up as few bits as possible
+    #[default]
+    Packed,
+    /// Tags take up a multiple of 4 bits
+    Nibble,
+    /// Tags take up a multiple of 8 bits
+    Byte,
+}
+
+impl TagAlign {
+    /// Rounds up the number of bits a tag needs to this alignment
+    pub fn align(self, bits: usize) -> usize {
+        match self {
+            TagAlign::Packed => bits,
+            TagAlign::Nibble => bits.div_ceil(4) * 4,
+            TagAlign::Byte => bits.div_ceil(8) * 8,
+        }
+    }
+}
+
+/// How the data of each variant is laid out, from `#[phenotype(layout = "...")]`
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// Fields are packed together with no padding, and may be unaligned
+    #[default]
+    Packed,
+    /// Fields are aligned, with padding like a `#[repr(C)]` struct
+    Aligned,
+    /// Aligned if that takes up no more space than packed, decided when the
+    /// sizes are known
+    Auto,
+}
+
+/// Whether the enum is stored whole, from `#[phenotype(pass_through = "...")]`
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum PassThrough {
+    /// The enum is split into a tag and data
+    #[default]
+    Never,
+    /// The enum is stored whole if splitting it wouldn't save space, decided
+    /// when the sizes are known
+    Auto,
+    /// The enum is stored whole, with no tag
+    Always,
+}
+
+/// What kind of value a field packed into the tag holds
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Bool,
+    Unsigned,
+    Signed,
+}
+
+/// A field packed into the tag, from `#[phenotype(bits = N)]` on the field
+#[derive(Clone, Copy)]
+pub struct Inline {
+    pub kind: Kind,
+    /// The number of bits the field is stored in
+    pub bits: usize,
+    /// The number of bits in the field's type, or `None` for `usize` and
+    /// `isize`, whose size depends on the target
+    pub width: Option<usize>,
+}
+
+/// The options set with `#[phenotype(...)]` on the enum
+#[derive(Default)]
+pub struct Options {
+    pub tag_align: TagAlign,
+    pub layout: Layout,
+    pub pass_through: PassThrough,
+}
+
+pub fn parse_options(attrs: &[Attribute]) -> Options {
+    let mut options = Options::default();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("phenotype")) {
+        let list = match attr.parse_meta() {
+            Ok(Meta::List(list)) => list,
+            Ok(meta) => abort!(meta, "malformed `phenotype` attribute"; note = NOTE),
+            Err(err) => abort!(err.span(), "{}", err; note = NOTE),
+        };
+        for nested in list.nested {
+            let option = match nested {
+                NestedMeta::Meta(Meta::NameValue(option)) => option,
+                other => abort!(other, "malformed `phenotype` attribute"; note = NOTE),
+            };
+            let value = match &option.lit {
+                Lit::Str(value) => value,
+                other => abort!(other, "`phenotype` options must be strings"; note = NOTE),
+            };
+            if option.path.is_ident("tag_align") {
+                options.tag_align = match value.value().as_str() {
+                    "packed" => TagAlign::Packed,
+                    "nibble" => TagAlign::Nibble,
+                    "byte" => TagAlign::Byte,
+                    _ => abort!(value, "unknown tag alignment"; note = NOTE),
+                };
+            } else if option.path.is_ident("layout") {
+                options.layout = match value.value().as_str() {
+                    "packed" => Layout::Packed,
+                    "aligned" => Layout::Aligned,
+                    "auto" => Layout::Auto,
+                    _ => abort!(value, "unknown layout"; note = NOTE),
+                };
+            } else if option.path.is_ident("pass_through") {
+                options.pass_through = match value.value().as_str() {
+                    "never" => PassThrough::Never,
+                    "auto" => PassThrough::Auto,
+                    "always" => PassThrough::Always,
+                    _ => abort!(value, "unknown pass through mode"; note = NOTE),
+                };
+            } else {
+                abort!(option.path, "unknown `phenotype` option"; note = NOTE)
+            }
+        }
+    }
+    options
+}
+
+const FIELD_NOTE: &str = "expected `bits = N` on a `bool` or integer field";
+
+/// Returns how a field is packed into the tag, or `None` if it's stored with
+/// the rest of the variant's data
+pub fn parse_field(field: &Field) -> Option<Inline> {
+    let mut bits = None;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("phenotype"))
+    {
+        let list = match attr.parse_meta() {
+            Ok(Meta::List(list)) => list,
+            Ok(meta) => abort!(meta, "malformed `phenotype` attribute"; note = FIELD_NOTE),
+            Err(err) => abort!(err.span(), "{}", err; note = FIELD_NOTE),
+        };
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(option)) if option.path.is_ident("bits") => {
+                    bits = match &option.lit {
+                        Lit::Int(lit) => match lit.base10_parse::<usize>() {
+                            Ok(n) if n > 0 => Some((n, lit.span())),
+                            _ => abort!(lit, "a field must take up at least one bit"),
+                        },
+                        other => abort!(other, "`bits` must be an integer"; note = FIELD_NOTE),
+                    }
+                }
+                other => abort!(other, "unknown `phenotype` field option"; note = FIELD_NOTE),
+            }
+        }
+    }
+    let (bits, span) = bits?;
+
+    let ty = match &field.ty {
+        Type::Path(path) if path.qself.is_none() => path.path.get_ident(),
+        _ => None,
+    };
+    let name = ty.map(|ty| ty.to_string()).unwrap_or_default();
+    let (kind, width) = match name.as_str() {
+        "bool" => (Kind::Bool, Some(1)),
+        "u8" => (Kind::Unsigned, Some(8)),
+        "u16" => (Kind::Unsigned, Some(16)),
+        "u32" => (Kind::Unsigned, Some(32)),
+        "u64" => (Kind::Unsigned, Some(64)),
+        "u128" => (Kind::Unsigned, Some(128)),
+        "usize" => (Kind::Unsigned, None),
+        "i8" => (Kind::Signed, Some(8)),
+        "i16" => (Kind::Signed, Some(16)),
+        "i32" => (Kind::Signed, Some(32)),
+        "i64" => (Kind::Signed, Some(64)),
+        "i128" => (Kind::Signed, Some(128)),
+        "isize" => (Kind::Signed, None),
+        _ => abort!(
+            field.ty,
+            "only `bool` and integer fields can be packed into the tag"
+        ),
+    };
+    if width.is_some_and(|width| bits > width) {
+        abort!(span, "`{}` fields have only {} bits", name, width.unwrap())
+    }
+    Some(Inline { kind, bits, width })
+}
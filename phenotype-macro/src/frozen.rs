@@ -0,0 +1,29 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Field, Generics, Ident};
+
+// A type is `Frozen` if all of its fields are. The bounds go on the impl, so a
+// field like `Cell<u8>` makes it not `Frozen` rather than failing to compile.
+// rustc rejects a bound that is trivially false, like `Cell<u8>: Frozen`, unless
+// it's higher-ranked, so each one gets a lifetime it doesn't use.
+
+/// An impl of `Frozen` for `ident` that holds exactly when it does for each of
+/// `fields`
+pub fn frozen_impl<'a>(
+    ident: &Ident,
+    generics: &Generics,
+    fields: impl Iterator<Item = &'a Field>,
+) -> TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let predicates = where_clause
+        .into_iter()
+        .flat_map(|clause| &clause.predicates);
+    let tys = fields.map(|field| &field.ty);
+    quote! {
+        unsafe impl #impl_generics ::phenotype_internal::Frozen for #ident #ty_generics
+        where
+            #(#predicates,)*
+            #(for<'__frozen> #tys: ::phenotype_internal::Frozen,)*
+        {}
+    }
+}
@@ -0,0 +1,93 @@
+use crate::attr::{self, Inline, Kind};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{punctuated::Punctuated, token::Comma, Field, Fields, Variant};
+
+// Small fields can be packed into the tag with `#[phenotype(bits = N)]`, in the
+// bits above the ones that say which variant it is. The first such field takes
+// the lowest of them. These fields are left out of the auxiliary struct, so a
+// variant whose fields are all packed carries no data at all.
+
+/// How each of a variant's fields is packed into the tag, if it is
+pub fn fields(variant: &Variant) -> Vec<Option<Inline>> {
+    variant.fields.iter().map(attr::parse_field).collect()
+}
+
+/// The number of bits a variant packs into the tag
+pub fn bits(variant: &Variant) -> usize {
+    fields(variant)
+        .iter()
+        .flatten()
+        .map(|inline| inline.bits)
+        .sum()
+}
+
+/// The variant without the fields packed into the tag, i.e. the ones its
+/// auxiliary struct holds. If there are none left, it's a unit variant
+pub fn stored(variant: &Variant) -> Variant {
+    let keep = |fields: &Punctuated<Field, Comma>| {
+        fields
+            .iter()
+            .filter(|field| attr::parse_field(field).is_none())
+            .cloned()
+            .collect::<Punctuated<Field, Comma>>()
+    };
+    let mut stored = variant.clone();
+    match &mut stored.fields {
+        Fields::Named(named) => named.named = keep(&named.named),
+        Fields::Unnamed(unnamed) => unnamed.unnamed = keep(&unnamed.unnamed),
+        Fields::Unit => {}
+    }
+    if stored.fields.is_empty() {
+        stored.fields = Fields::Unit;
+    }
+    stored
+}
+
+/// Panics if the field `value` points to doesn't fit in its bits. `name` is
+/// what to call it in the message, like `Level::Custom.0`
+pub fn check(inline: Inline, value: &TokenStream, name: &str) -> TokenStream {
+    let bits = inline.bits;
+    let message = format!("`{name}` doesn't fit in {bits} bits");
+    match inline.kind {
+        // Every value fits when the field gets all of its type's bits
+        _ if inline.width == Some(bits) => quote!(),
+        Kind::Bool => quote!(),
+        Kind::Unsigned => quote! {
+            ::core::assert!((*#value as u128) >> #bits == 0, #message);
+        },
+        // The bits above the sign bit must all be copies of it
+        Kind::Signed => quote! {
+            let high = (*#value as i128) >> (#bits - 1);
+            ::core::assert!(high == 0 || high == -1, #message);
+        },
+    }
+}
+
+/// The field `value` points to, moved to `offset` bits up the tag
+pub fn pack(inline: Inline, value: &TokenStream, offset: usize) -> TokenStream {
+    let bits = inline.bits;
+    match inline.kind {
+        Kind::Bool => quote!((*#value as usize) << #offset),
+        // Signed values are sign extended, so the bits above them are cut off
+        Kind::Unsigned | Kind::Signed => quote! {
+            ((*#value as usize) & (usize::MAX >> (usize::BITS as usize - #bits))) << #offset
+        },
+    }
+}
+
+/// The field of type `ty` at `offset` bits up `tag`
+pub fn unpack(inline: Inline, ty: &syn::Type, offset: usize) -> TokenStream {
+    let bits = inline.bits;
+    match inline.kind {
+        Kind::Bool => quote!((tag >> #offset) & 1 != 0),
+        Kind::Unsigned => quote! {
+            ((tag >> #offset) & (usize::MAX >> (usize::BITS as usize - #bits))) as #ty
+        },
+        // Shift the field to the top of the word, then back down to extend its sign
+        Kind::Signed => quote! {
+            ((((tag >> #offset) << (usize::BITS as usize - #bits)) as isize)
+                >> (usize::BITS as usize - #bits)) as #ty
+        },
+    }
+}
@@ -1,8 +1,9 @@
+use attr::{Layout, PassThrough};
 use proc_macro2::TokenStream;
 use proc_macro_error::{abort, proc_macro_error};
 use quote::{format_ident, quote};
 use std::collections::HashMap;
-use syn::{parse_macro_input, DeriveInput, FieldsNamed, FieldsUnnamed, Generics, Ident, Variant};
+use syn::{parse_macro_input, DeriveInput, FieldsNamed, Generics, Ident, Variant};
 
 const NOTE: &str = "can only derive phenotype on enums";
 
@@ -11,6 +12,15 @@ type Tag = usize;
 /// Holds the logic for parsing generics
 mod generic;
 
+/// Holds the logic for parsing `#[phenotype(...)]` options
+mod attr;
+
+/// Holds the logic for packing small fields into the tag
+mod inline;
+
+/// Holds the logic for implementing `Frozen`
+mod frozen;
+
 /// Condensed derive input; just the stuff we need
 struct Condensed<'a> {
     name: Ident,
@@ -27,13 +37,68 @@ fn log2(x: usize) -> u32 {
     num_bits::<usize>() as u32 - x.leading_zeros() - 1
 }
 
-#[proc_macro_derive(Phenotype)]
+/// The number of bits needed to tell the variants of an enum apart
+fn variant_bits(data: &Condensed) -> usize {
+    if data.variants.is_empty() {
+        0
+    } else if data.variants.len() == 1 {
+        // This avoids having to check everywhere if T::BITS == 1,
+        // which is easy to forget and can easily cause panics,
+        // for the cheap cost of one bit
+        1
+    } else {
+        let log = log2(data.variants.len());
+        let pow = 2usize.pow(log);
+
+        // if 2 ** log is less than the number of variants, that means
+        // the log rounded down (i.e. the float version was something like
+        // 1.4, which became 1)
+        //
+        // We round up because we always carry the extra bits, i.e.
+        // 7 variants needs 2.8 bits but we carry 3
+        (if pow < data.variants.len() {
+            log + 1
+        } else {
+            log
+        }) as usize
+    }
+}
+
+/// Derives `Phenotype` for an enum.
+///
+/// Tags take up as few bits as possible by default. To trade some space for
+/// cheaper tag access, they can be aligned with
+/// `#[phenotype(tag_align = "nibble")]`, which rounds them up to a multiple of 4
+/// bits, or `#[phenotype(tag_align = "byte")]`, which rounds them up to a
+/// multiple of 8. Aligned tags never straddle a word boundary.
+///
+/// Each variant's data is packed, with no padding, by default, so fields may
+/// be unaligned. `#[phenotype(layout = "aligned")]` lays them out like a
+/// `#[repr(C)]` struct instead, trading padding for aligned fields.
+/// `#[phenotype(layout = "auto")]` picks aligned if it takes up no more space.
+/// The sizes of a generic enum's fields aren't known until it's instantiated,
+/// so there `auto` is always packed.
+///
+/// Enums whose tag rustc already hides in a niche, like `Option<&T>`, are
+/// bigger split up than whole. `#[phenotype(pass_through = "always")]` stores
+/// them whole, as a single variant with no tag bits, and
+/// `#[phenotype(pass_through = "auto")]` does so only if splitting wouldn't
+/// save space. As with `layout`, `auto` never passes a generic enum through.
+///
+/// A `bool` or integer field that only needs a few bits can be packed into the
+/// tag, next to the bits that say which variant it is, with
+/// `#[phenotype(bits = N)]` on the field. It then takes up no space in the
+/// data, and an enum whose fields are all packed is nothing but tags. Storing
+/// a value that doesn't fit in `N` bits panics, dropping the value as usual.
+/// Signed fields are stored in two's complement, so 4 bits hold `-8..=7`.
+#[proc_macro_derive(Phenotype, attributes(phenotype))]
 #[proc_macro_error]
 pub fn phenotype(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
 
     let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
     let ident = ast.ident.clone();
+    let options = attr::parse_options(&ast.attrs);
 
     // Verify we have an enum
     let enumb = match ast.data {
@@ -70,54 +135,109 @@ pub fn phenotype(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
         )
     }
 
-    let auxiliaries = make_auxiliaries(&data);
+    let layout = match options.layout {
+        Layout::Auto if ast.generics.params.iter().next().is_some() => Layout::Packed,
+        layout => layout,
+    };
 
-    let cleave_impl = cleave_impl(&data);
+    let auxiliaries = make_auxiliaries(&data, layout);
 
-    let reknit_impl = reknit_impl(&data);
+    let variant_bits = variant_bits(&data);
 
-    let bits = {
-        if data.variants.is_empty() {
-            0
-        } else if data.variants.len() == 1 {
-            // This avoids having to check everywhere if T::BITS == 1,
-            // which is easy to forget and can easily cause panics,
-            // for the cheap cost of one bit
-            1
-        } else {
-            let log = log2(data.variants.len());
-            let pow = 2usize.pow(log);
-
-            // if 2 ** log is less than the number of variants, that means
-            // the log rounded down (i.e. the float version was something like
-            // 1.4, which became 1)
-            //
-            // We round up because we always carry the extra bits, i.e.
-            // 7 variants needs 2.8 bits but we carry 3
-            (if pow < data.variants.len() {
-                log + 1
-            } else {
-                log
-            }) as usize
+    // Fields packed into the tag go above the variant, so the tag is as big as
+    // the variant that packs the most
+    let (inline_bits, widest) = data
+        .variants
+        .values()
+        .map(|var| (inline::bits(var), var))
+        .max_by_key(|(bits, _)| *bits)
+        .unwrap();
+    if variant_bits + inline_bits > 64 {
+        abort!(
+            widest,
+            "the fields of `{}` packed into the tag take up {} bits",
+            widest.ident,
+            inline_bits;
+            note = "the tag, with {} bits for the variant, has to fit in a `usize`", variant_bits
+        )
+    }
+    let bits = options.tag_align.align(variant_bits + inline_bits);
+
+    let union_ident = format_ident!("__PhenotypeInternal{}Data", data.name);
+
+    // A constant that says whether the enum is stored whole, if it might be
+    let (pass, pass_def) = match options.pass_through {
+        PassThrough::Never => (None, None),
+        PassThrough::Always => (Some(quote!(true)), None),
+        // The sizes of a generic enum's fields aren't known yet
+        PassThrough::Auto if ast.generics.params.iter().next().is_some() => (None, None),
+        PassThrough::Auto => {
+            let cond = format_ident!("__PhenotypeInternal{}PassThrough", data.name);
+            let def = quote! {
+                // Whether splitting the enum takes up at least as many bits as
+                // storing it whole, like when rustc stores its tag in a niche
+                #[allow(non_upper_case_globals)]
+                const #cond: bool = ::core::mem::size_of::<#union_ident>() * 8 + #bits
+                    >= ::core::mem::size_of::<#ident>() * 8;
+            };
+            (Some(quote!(#cond)), Some(def))
         }
     };
+    // Picks what to use when the enum is stored whole, if it might be
+    let whole_or = |whole: TokenStream, split: TokenStream| match &pass {
+        Some(pass) => quote!(if #pass { #whole } else { #split }),
+        None => split,
+    };
+
+    let cleave_impl = cleave_impl(&data, layout, pass.as_ref());
+
+    let reknit_impl = reknit_impl(&data, layout, pass.as_ref());
 
     let num_variants = data.variants.len();
 
-    let union_ident = format_ident!("__PhenotypeInternal{}Data", data.name);
+    // What each variant stores in its auxiliary struct, in tag order
+    let stored = (0..num_variants)
+        .map(|tag| inline::stored(&data.variants[&tag]))
+        .collect::<Vec<_>>();
+
+    // Whether each variant carries data, in tag order
+    let has_data = stored
+        .iter()
+        .map(|var| !matches!(var.fields, syn::Fields::Unit))
+        .collect::<Vec<_>>();
+
+    // The size of each variant's auxiliary struct, in tag order
+    let payload_sizes = stored
+        .iter()
+        .map(|var| match var.fields {
+            syn::Fields::Unit => quote!(0),
+            _ => {
+                let aux = aux_type(data.name.clone(), var, layout);
+                quote!(::core::mem::size_of::<#aux>())
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let variants = (0..num_variants)
+        .map(|tag| variant_info(tag, &data.variants[&tag]))
+        .collect::<Vec<_>>();
 
     let peapod_size = match data.generics.type_params().next() {
         Some(_) => quote!(None),
         // No generics
         None => {
             let bytes = bits / 8
-                + if bits % 8 == 0 {
+                + if bits.is_multiple_of(8) {
                     0
                 } else {
                     // Add an extra byte if there are remaining bits (a partial byte)
                     1
                 };
-            quote!(Some({ #bytes + ::core::mem::size_of::<#union_ident>() }))
+            let size = whole_or(
+                quote!(::core::mem::size_of::<#ident>()),
+                quote!(#bytes + ::core::mem::size_of::<#union_ident>()),
+            );
+            quote!(Some({ #size }))
         }
     };
 
@@ -128,7 +248,7 @@ pub fn phenotype(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
             quote!(
                 Some(
                     // unwrap isn't const
-                    match <Self as Phenotype>::PEAPOD_SIZE {
+                    match <Self as ::phenotype_internal::Phenotype>::PEAPOD_SIZE {
                         Some(size) => size <= ::core::mem::size_of::<#ident>(),
                         // Unreachable as if there are not generics, PEAPOD_SIZE
                         // is `Some`
@@ -140,13 +260,61 @@ pub fn phenotype(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
         }
     };
 
+    // Stored whole, the enum is one variant with no tag bits
+    let name = ident.to_string();
+    let num_variants = whole_or(quote!(1), quote!(#num_variants));
+    let bits = match inline_bits {
+        0 => quote!(#bits),
+        // The macro can only check the tag fits in 64 bits
+        _ => {
+            let message = format!("the tag of `{ident}` doesn't fit in a `usize`");
+            quote!({
+                ::core::assert!(#bits <= usize::BITS as usize, #message);
+                #bits
+            })
+        }
+    };
+    let bits = whole_or(quote!(0), bits);
+    let variant_bits = whole_or(quote!(0), quote!(#variant_bits));
+    let has_data = whole_or(quote!(&[true]), quote!(&[#(#has_data),*]));
+    let payload_sizes = whole_or(
+        quote!(&[::core::mem::size_of::<Self>()]),
+        quote!(&[#(#payload_sizes),*]),
+    );
+    let variants = whole_or(
+        quote! {
+            &[::phenotype_internal::VariantInfo {
+                name: #name,
+                tag: 0,
+                field_names: &[],
+                field_types: &[],
+                payload_size: ::core::mem::size_of::<Self>(),
+                inline_bits: 0,
+                align: ::core::mem::align_of::<Self>(),
+            }]
+        },
+        quote!(&[#(#variants),*]),
+    );
+
+    let frozen = frozen::frozen_impl(
+        &ident,
+        data.generics,
+        data.variants.values().flat_map(|var| &var.fields),
+    );
+
     quote! {
         #auxiliaries
-        unsafe impl #impl_generics Phenotype for #ident #ty_generics
+        #pass_def
+        #frozen
+        unsafe impl #impl_generics ::phenotype_internal::Phenotype for #ident #ty_generics
             #where_clause
         {
             const NUM_VARIANTS: usize = #num_variants;
             const BITS: usize = #bits;
+            const VARIANT_BITS: usize = #variant_bits;
+            const HAS_DATA: &'static [bool] = #has_data;
+            const PAYLOAD_SIZES: &'static [usize] = #payload_sizes;
+            const VARIANTS: &'static [::phenotype_internal::VariantInfo] = #variants;
             const PEAPOD_SIZE: Option<usize> = #peapod_size;
             const IS_MORE_COMPACT: Option<bool> = #is_more_compact;
             #cleave_impl
@@ -156,59 +324,171 @@ pub fn phenotype(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     .into()
 }
 
-fn reknit_impl(data: &Condensed) -> TokenStream {
+/// Describe a variant as a `VariantInfo`
+fn variant_info(tag: Tag, var: &Variant) -> TokenStream {
+    let name = var.ident.to_string();
+    let inline_bits = inline::bits(var);
+    let field_names = var
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| match &f.ident {
+            Some(ident) => ident.to_string(),
+            None => i.to_string(),
+        })
+        .collect::<Vec<_>>();
+    let tys = var.fields.iter().map(|f| &f.ty).collect::<Vec<_>>();
+    let field_types = tys.iter().map(|ty| type_name(ty));
+    quote! {
+        ::phenotype_internal::VariantInfo {
+            name: #name,
+            tag: #tag,
+            field_names: &[#(#field_names),*],
+            field_types: &[#(#field_types),*],
+            payload_size: <Self as ::phenotype_internal::Phenotype>::PAYLOAD_SIZES[#tag],
+            inline_bits: #inline_bits,
+            align: {
+                #[allow(unused_mut)]
+                let mut align = 1;
+                #(
+                    if ::core::mem::align_of::<#tys>() > align {
+                        align = ::core::mem::align_of::<#tys>();
+                    }
+                )*
+                align
+            },
+        }
+    }
+}
+
+/// Render a type the way it would be written, e.g. `Vec<u8>` rather than the
+/// `Vec < u8 >` that printing its tokens gives
+fn type_name(ty: &syn::Type) -> String {
+    let mut name = quote!(#ty).to_string();
+    for (spaced, tight) in [
+        (" <", "<"),
+        ("< ", "<"),
+        (" >", ">"),
+        (" ,", ","),
+        (" ;", ";"),
+        (" ::", "::"),
+        (":: ", "::"),
+        ("( ", "("),
+        (" )", ")"),
+        ("[ ", "["),
+        (" ]", "]"),
+        ("& ", "&"),
+        ("* ", "*"),
+    ] {
+        name = name.replace(spaced, tight);
+    }
+    // Calls like `Fn (u8)` are tight, but `*const (u8, u16)` isn't
+    let mut tight = String::with_capacity(name.len());
+    for (i, c) in name.char_indices() {
+        if c == ' ' && name[i..].starts_with(" (") {
+            let word = tight
+                .rsplit(|c: char| !c.is_alphanumeric() && c != '_')
+                .next()
+                .unwrap_or_default();
+            if !word.is_empty() && !matches!(word, "const" | "mut" | "dyn" | "impl") {
+                continue;
+            }
+        }
+        tight.push(c);
+    }
+    tight
+}
+
+fn reknit_impl(data: &Condensed, layout: Layout, pass: Option<&TokenStream>) -> TokenStream {
     let mut arms = Vec::with_capacity(data.variants.len());
 
     let ident = &data.name;
+    let variant_bits = variant_bits(data);
 
     // We're going to turn each variant into a match that handles that variant's case
     for (tag, var) in &data.variants {
-        let struct_name = format_ident!("__PhenotypeInternal{}{}Data", data.name, var.ident);
+        let stored = inline::stored(var);
+        let aux = aux_type(data.name.clone(), &stored, layout);
         let var_ident = &var.ident;
-        let var_generics = generic::variant_generics(data.generics, var);
-        arms.push(match &var.fields {
-            syn::Fields::Named(FieldsNamed { named, .. }) => {
-                let struct_fields = named
-                    .iter()
-                    .map(|f| f.ident.clone().unwrap())
-                    .collect::<Vec<_>>();
-                quote! {
-                    #tag => {
-                        // SAFETY: Safe because the tag guarantees that we are reading the correct field
-                        let data = ::core::mem::ManuallyDrop::<#struct_name :: #var_generics>::into_inner(
-                            unsafe { value.#var_ident }
-                        );
-                        #ident::#var_ident { #(#struct_fields: data.#struct_fields),* }
-                    }
+
+        // Fields packed into the tag are shifted back out of it, and the rest
+        // are read out of the auxiliary struct. The struct may be packed, so
+        // its fields may not be aligned. We read them through raw pointers,
+        // never references.
+        // SAFETY: each field is initialized and read exactly once, and `data`
+        // is never dropped
+        let mut offset = variant_bits;
+        let mut stored_fields = stored.fields.iter().enumerate();
+        let values = var
+            .fields
+            .iter()
+            .zip(inline::fields(var))
+            .map(|(field, inline)| match inline {
+                Some(inline) => {
+                    offset += inline.bits;
+                    inline::unpack(inline, &field.ty, offset - inline.bits)
                 }
-            }
-            syn::Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => {
-                // This produces the indexes we use to extract the data from the struct
-                let struct_field_placeholders = (0..unnamed.len()).map(syn::Index::from);
-                quote! {
-                    #tag => {
-                        // SAFETY: Safe because the tag guarantees that we are reading the correct field
-                        let data = ::core::mem::ManuallyDrop::<#struct_name :: #var_generics>::into_inner(
-                            unsafe { value.#var_ident }
-                        );
-                        #ident::#var_ident ( #(data.#struct_field_placeholders),* )
+                None => {
+                    let (i, stored_field) = stored_fields.next().unwrap();
+                    let member = match &stored_field.ident {
+                        Some(ident) => quote!(#ident),
+                        None => {
+                            let index = syn::Index::from(i);
+                            quote!(#index)
+                        }
+                    };
+                    quote! {
+                        unsafe { ::core::ptr::read_unaligned(::core::ptr::addr_of!(data.#member)) }
                     }
                 }
+            })
+            .collect::<Vec<_>>();
+
+        let read = match stored.fields {
+            syn::Fields::Unit => quote!(),
+            // SAFETY: Safe because the tag guarantees that we are reading the correct field
+            _ => quote! {
+                let data: ::core::mem::ManuallyDrop<#aux> = unsafe { value.#var_ident };
+            },
+        };
+        let build = match &var.fields {
+            syn::Fields::Named(FieldsNamed { named, .. }) => {
+                let names = named.iter().map(|f| f.ident.as_ref().unwrap());
+                quote!(#ident::#var_ident { #(#names: #values),* })
             }
-            syn::Fields::Unit => {
-                quote! {
-                    #tag => {
-                        #ident::#var_ident
-                    }
-                }
+            syn::Fields::Unnamed(_) => quote!(#ident::#var_ident(#(#values),*)),
+            syn::Fields::Unit => quote!(#ident::#var_ident),
+        };
+        arms.push(quote! {
+            #tag => {
+                #read
+                #build
             }
         })
     }
 
     let generics = data.generics.split_for_impl().1;
+    let union_ident = format_ident!("__PhenotypeInternal{ident}Data");
+    // When the enum might be stored whole, `Value` is either the union or the
+    // enum, and only one of these conversions is ever run
+    let unwrap = pass.map(|pass| {
+        quote! {
+            if #pass {
+                // SAFETY: stored whole, `Value` is `ManuallyDrop<Self>`
+                return ::core::mem::ManuallyDrop::into_inner(unsafe {
+                    ::core::mem::transmute_copy::<_, ::core::mem::ManuallyDrop<Self>>(&value)
+                });
+            }
+            // SAFETY: otherwise `Value` is the union
+            let value = unsafe {
+                ::core::mem::transmute_copy::<_, #union_ident #generics>(&value)
+            };
+        }
+    });
     quote! {
-        unsafe fn reknit(tag: usize, value: <Self as Phenotype>::Value) -> #ident #generics {
-            match tag {
+        unsafe fn reknit(tag: usize, value: <Self as ::phenotype_internal::Phenotype>::Value) -> #ident #generics {
+            #unwrap
+            match <Self as ::phenotype_internal::Phenotype>::variant(tag) {
                 #(#arms),*
                 // There should be no other cases, as there are no other variants
                 _ => ::core::unreachable!()
@@ -218,106 +498,173 @@ fn reknit_impl(data: &Condensed) -> TokenStream {
 }
 
 /// Implement the `value` trait method
-fn cleave_impl(data: &Condensed) -> proc_macro2::TokenStream {
+fn cleave_impl(
+    data: &Condensed,
+    layout: Layout,
+    pass: Option<&TokenStream>,
+) -> proc_macro2::TokenStream {
     let ident = &data.name;
     let union_ident = format_ident!("__PhenotypeInternal{ident}Data");
+    let variant_bits = variant_bits(data);
 
     // Snippet to extract data out of each field
     let mut arms: Vec<proc_macro2::TokenStream> = Vec::with_capacity(data.variants.len());
+    // Snippet to check that each field packed into the tag fits
+    let mut check_arms = Vec::with_capacity(data.variants.len());
 
     let generics = data.generics.split_for_impl().1;
 
     // Like `reknit_impl`, we produce a match arm for each variant
     for (tag, var) in &data.variants {
         let var_ident = &var.ident;
-        let struct_name = format_ident!("__PhenotypeInternal{ident}{var_ident}Data");
-
-        let var_generics = generic::variant_generics(data.generics, var);
-        arms.push(match &var.fields {
-            syn::Fields::Named(FieldsNamed { named, .. }) => {
-                // Capture each enum field (named), use it's ident to capture it's value
-                let fields = named.iter().map(|f| f.ident.clone()).collect::<Vec<_>>();
-                quote! {
-                    #ident::#var_ident {#(#fields),*} => (#tag,
-                        #union_ident {
-                            #var_ident: ::core::mem::ManuallyDrop::new(#struct_name :: #var_generics {
-                                // We've wrapped the enum that was passed in in a ManuallyDrop,
-                                // and now we read each field with ptr::read.
-
-                                // We wrap the enum that was passed in a ManuallyDrop to prevent
-                                // double drops.
-
-                                // We have to ptr::read because you can't move out of a
-                                // type that implements `Drop`
-                                // SAFETY: we are reading from a reference
-                                #(#fields: unsafe { ::core::ptr::read(#fields) }),*
-                            })
-                        }
-                    )
+        let stored = inline::stored(var);
+        let aux = aux_type(ident.clone(), &stored, layout);
+
+        // Capture each field by reference: named fields by their idents, and
+        // unnamed ones as _0, _1, ...
+        let (bindings, names) = var
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(i, f)| match &f.ident {
+                Some(field) => (quote!(#field), format!("{ident}::{var_ident}::{field}")),
+                None => {
+                    let field = format_ident!("_{i}");
+                    (quote!(#field), format!("{ident}::{var_ident}.{i}"))
                 }
-            }
-            syn::Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => {
-                // For each field (unnamed), produce an ident like _0, _1, ... so we can capture the value
-                let fields = (0..unnamed.iter().len())
-                    .map(|i| format_ident!("_{i}"))
-                    .collect::<Vec<_>>();
-                quote! {
-                    #ident::#var_ident(#(#fields),*) => (#tag,
-                        #union_ident {
-                            #var_ident: ::core::mem::ManuallyDrop::new(
-                                #struct_name :: #var_generics (
-                                    // We've wrapped the enum that was passed in in a ManuallyDrop,
-                                    // and now we read each field with ptr::read.
-
-                                    // We wrap the enum that was passed in a ManuallyDrop to prevent
-                                    // double drops.
-
-                                    // We have to ptr::read because you can't move out of a
-                                    // type that implements `Drop`
-                                    // SAFETY: we are reading from a reference
-                                    #( unsafe { ::core::ptr::read(#fields) }),*
-                                )
-                            )
-                        }
-                    )
+            })
+            .unzip::<_, _, Vec<_>, Vec<_>>();
+        let pattern = |bindings: &[TokenStream]| match &var.fields {
+            syn::Fields::Named(_) => quote!(#ident::#var_ident { #(#bindings),* }),
+            syn::Fields::Unnamed(_) => quote!(#ident::#var_ident(#(#bindings),*)),
+            syn::Fields::Unit => quote!(#ident::#var_ident),
+        };
+
+        // Fields packed into the tag are checked before `self` is taken apart,
+        // so a panic drops it like any other value. The other fields aren't
+        // bound there
+        let (mut checks, mut packed, mut reads) = (vec![], vec![], vec![]);
+        let mut check_bindings = Vec::with_capacity(bindings.len());
+        let mut offset = variant_bits;
+        for ((binding, name), inline) in bindings.iter().zip(&names).zip(inline::fields(var)) {
+            check_bindings.push(match (inline, &var.fields) {
+                (Some(_), _) => binding.clone(),
+                (None, syn::Fields::Named(_)) => quote!(#binding: _),
+                (None, _) => quote!(_),
+            });
+            match inline {
+                Some(inline) => {
+                    checks.push(inline::check(inline, binding, name));
+                    packed.push(inline::pack(inline, binding, offset));
+                    offset += inline.bits;
                 }
+                // We've wrapped the enum that was passed in in a ManuallyDrop,
+                // and now we read each field with ptr::read.
+
+                // We wrap the enum that was passed in a ManuallyDrop to prevent
+                // double drops.
+
+                // We have to ptr::read because you can't move out of a
+                // type that implements `Drop`
+                // SAFETY: we are reading from a reference
+                None => reads.push(quote!(unsafe { ::core::ptr::read(#binding) })),
             }
-            syn::Fields::Unit => quote! {
-                #ident::#var_ident => (#tag, #union_ident { #var_ident: () }) // Doesn't contain data
+        }
+
+        let value = match stored.fields {
+            // Doesn't contain data
+            syn::Fields::Unit => quote!(#union_ident { #var_ident: () }),
+            _ => quote! {
+                #union_ident {
+                    #var_ident: ::core::mem::ManuallyDrop::new(<#aux>::new(#(#reads),*))
+                }
             },
-        })
+        };
+        let check_pattern = pattern(&check_bindings);
+        check_arms.push(quote!(#check_pattern => { #(#checks)* }));
+        let pattern = pattern(&bindings);
+        arms.push(quote!(#pattern => (#tag #(| #packed)*, #value)))
     }
-    quote! {
-        type Value = #union_ident #generics;
-        fn cleave(self) -> (usize, <Self as Phenotype>::Value) {
-            match &*::core::mem::ManuallyDrop::new(self) {
-                #(#arms),*
-            }
+    // Only enums with fields packed into the tag have anything to check
+    let checks = data
+        .variants
+        .values()
+        .any(|var| inline::bits(var) > 0)
+        .then(|| quote!(match &self { #(#check_arms),* }));
+    let split = quote! {
+        match &*::core::mem::ManuallyDrop::new(self) {
+            #(#arms),*
         }
+    };
+    match pass {
+        None => quote! {
+            type Value = #union_ident #generics;
+            fn cleave(self) -> (usize, <Self as ::phenotype_internal::Phenotype>::Value) {
+                #checks
+                #split
+            }
+        },
+        // See `reknit_impl`
+        Some(pass) => quote! {
+            type Value = <::phenotype_internal::__private::Select<{ #pass }> as ::phenotype_internal::__private::Choose<
+                #union_ident #generics,
+                ::core::mem::ManuallyDrop<Self>,
+            >>::Out;
+            fn cleave(self) -> (usize, <Self as ::phenotype_internal::Phenotype>::Value) {
+                if #pass {
+                    let whole = ::core::mem::ManuallyDrop::new(self);
+                    // SAFETY: stored whole, `Value` is `ManuallyDrop<Self>`
+                    return (0, unsafe { ::core::mem::transmute_copy(&whole) });
+                }
+                #checks
+                let (tag, value): (usize, #union_ident #generics) = #split;
+                // SAFETY: otherwise `Value` is the union
+                (tag, unsafe { ::core::mem::transmute_copy(&value) })
+            }
+        },
     }
 }
 
-/// A struct that represents the data found in an enum
-struct Auxiliary {
-    ident: Ident,
-    // Tokens for the actual code of the struct
-    tokens: proc_macro2::TokenStream,
+/// The type of the auxiliary struct that holds a variant's data, with its
+/// fields' types filled in
+fn aux_type(enum_name: Ident, variant: &Variant, layout: Layout) -> TokenStream {
+    let var_ident = &variant.ident;
+    let packed = format_ident!("__PhenotypeInternal{enum_name}{var_ident}Data");
+    let types = generic::field_types(variant);
+    match layout {
+        Layout::Packed | Layout::Aligned => quote!(#packed :: #types),
+        Layout::Auto => {
+            // Both layouts are defined, and the constant picks one
+            let aligned = format_ident!("__PhenotypeInternal{enum_name}{var_ident}AlignedData");
+            let cond = format_ident!("__PhenotypeInternal{enum_name}Aligned");
+            quote! {
+                <::phenotype_internal::__private::Select<{ #cond }> as ::phenotype_internal::__private::Choose<
+                    #packed :: #types,
+                    #aligned :: #types,
+                >>::Out
+            }
+        }
+    }
 }
 
 /// Return an auxiliary struct that can hold the data from an enum variant.
-/// Returns `None` if the variant doesn't contain any data
+/// It has a `new` function that takes the fields in order, so it can be built
+/// when only its type is known. Returns `None` if the variant doesn't contain
+/// any data
 fn def_auxiliary_struct(
     variant: &Variant,
-    enum_name: &Ident,
-    all_generics: &Generics,
-) -> Option<Auxiliary> {
-    let field = &variant.ident;
-
-    let struct_name = format_ident!("__PhenotypeInternal{}{}Data", enum_name, field);
-
-    let generics = generic::variant_generics(all_generics, variant);
-
-    match &variant.fields {
+    struct_name: &Ident,
+    repr: TokenStream,
+) -> Option<TokenStream> {
+    let generics = generic::field_params(variant);
+    let types = (0..variant.fields.len())
+        .map(|i| format_ident!("__F{i}"))
+        .collect::<Vec<_>>();
+    let args = (0..variant.fields.len())
+        .map(|i| format_ident!("_{i}"))
+        .collect::<Vec<_>>();
+
+    let (def, build) = match &variant.fields {
         // Create a dummy struct that contains the named fields
         // We need the field idents and types so we can make pairs like:
         // ident1: type1
@@ -325,67 +672,115 @@ fn def_auxiliary_struct(
         // ...
         syn::Fields::Named(FieldsNamed { named, .. }) => {
             // Get the names of the fields
-            let idents = named.iter().map(|field| field.ident.as_ref().unwrap());
-            let types = named.iter().map(|field| &field.ty);
-            Some(Auxiliary {
-                ident: struct_name.clone(),
-                tokens: quote! {
-                    #[repr(packed)]
-                    struct #struct_name #generics {
-                        #(#idents: #types,)*
-                    }
-                },
-            })
+            let idents = named
+                .iter()
+                .map(|field| field.ident.as_ref().unwrap())
+                .collect::<Vec<_>>();
+            (
+                quote!(struct #struct_name #generics { #(#idents: #types,)* }),
+                quote!(Self { #(#idents: #args),* }),
+            )
         }
 
         // Create a dummy tuple struct that contains the fields
         // We only need the types so we can produce output like
         // type1, type2, ...
-        syn::Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => {
-            let types = unnamed.iter().map(|field| &field.ty);
-            Some(Auxiliary {
-                ident: struct_name.clone(),
-                tokens: quote! { #[repr(packed)] struct #struct_name #generics (#(#types,)*); },
-            })
-        }
+        syn::Fields::Unnamed(_) => (
+            quote!(struct #struct_name #generics (#(#types,)*);),
+            quote!(Self(#(#args),*)),
+        ),
 
         // No fields so we don't need to do anything
-        syn::Fields::Unit => None,
-    }
+        syn::Fields::Unit => return None,
+    };
+
+    Some(quote! {
+        #repr
+        #def
+
+        impl #generics #struct_name #generics {
+            #[allow(dead_code, clippy::too_many_arguments)]
+            #[inline]
+            fn new(#(#args: #types),*) -> Self {
+                #build
+            }
+        }
+    })
 }
 
 /// Define all auxiliary structs and the data enum
-fn make_auxiliaries(data: &Condensed) -> proc_macro2::TokenStream {
+fn make_auxiliaries(data: &Condensed, layout: Layout) -> proc_macro2::TokenStream {
     // Define the union that holds the data
-    let union_ident = format_ident!("__PhenotypeInternal{}Data", data.name);
+    let name = &data.name;
+    let union_ident = format_ident!("__PhenotypeInternal{name}Data");
 
     // Assorted data that goes into defining all the machinery
-    let (
-        mut struct_idents,
-        mut struct_defs,
-        mut field_idents,
-        mut empty_field_idents,
-        mut struct_generics,
-    ) = (vec![], vec![], vec![], vec![], vec![]);
+    let (mut struct_defs, mut field_idents, mut field_types, mut empty_field_idents) =
+        (vec![], vec![], vec![], vec![]);
+
+    // For `auto`, the sizes of each layout, to compare
+    let (mut packed_sizes, mut aligned_sizes, mut aligned_aligns) = (vec![], vec![], vec![]);
 
     for var in data.variants.values() {
-        if let Some(aux) = def_auxiliary_struct(var, &data.name, data.generics) {
-            struct_idents.push(aux.ident);
-            struct_defs.push(aux.tokens);
-            field_idents.push(var.ident.clone());
-            struct_generics.push(generic::variant_generics(data.generics, var));
+        // Fields packed into the tag aren't stored in the struct
+        let var = &inline::stored(var);
+        let var_ident = &var.ident;
+        let packed = format_ident!("__PhenotypeInternal{name}{var_ident}Data");
+        let repr = match layout {
+            Layout::Aligned => quote!(#[repr(C)]),
+            Layout::Packed | Layout::Auto => quote!(#[repr(C, packed)]),
+        };
+        if let Some(def) = def_auxiliary_struct(var, &packed, repr) {
+            struct_defs.push(def);
+            field_idents.push(var_ident.clone());
+            field_types.push(aux_type(name.clone(), var, layout));
+            if layout == Layout::Auto {
+                let aligned = format_ident!("__PhenotypeInternal{name}{var_ident}AlignedData");
+                struct_defs.extend(def_auxiliary_struct(var, &aligned, quote!(#[repr(C)])));
+                let types = generic::field_types(var);
+                let (packed, aligned) = (quote!(#packed :: #types), quote!(#aligned :: #types));
+                packed_sizes.push(quote!(::core::mem::size_of::<#packed>()));
+                aligned_sizes.push(quote!(::core::mem::size_of::<#aligned>()));
+                aligned_aligns.push(quote!(::core::mem::align_of::<#aligned>()));
+            }
         } else {
-            empty_field_idents.push(var.ident.clone())
+            empty_field_idents.push(var_ident.clone())
         }
     }
 
+    // Only non-generic enums are `auto`, so the sizes are all known here
+    let cond = (layout == Layout::Auto).then(|| {
+        let cond = format_ident!("__PhenotypeInternal{name}Aligned");
+        quote! {
+            // Whether aligning the data takes up no more space than packing it
+            #[allow(non_upper_case_globals)]
+            const #cond: bool = {
+                let (mut packed, mut aligned, mut align) = (0, 0, 1);
+                #(
+                    if #packed_sizes > packed {
+                        packed = #packed_sizes;
+                    }
+                    if #aligned_sizes > aligned {
+                        aligned = #aligned_sizes;
+                    }
+                    if #aligned_aligns > align {
+                        align = #aligned_aligns;
+                    }
+                )*
+                // The union is as big as its biggest field, rounded up to its alignment
+                aligned.div_ceil(align) * align <= packed
+            };
+        }
+    });
+
     let union_generics = data.generics.split_for_impl().1;
 
     quote! {
         #(#struct_defs)*
+        #cond
         #[allow(non_snake_case)]
         union #union_ident #union_generics {
-            #(#field_idents: ::core::mem::ManuallyDrop<#struct_idents #struct_generics>,)*
+            #(#field_idents: ::core::mem::ManuallyDrop<#field_types>,)*
             #(#empty_field_idents: (),)*
         }
     }
@@ -437,7 +832,7 @@ pub fn phenotype_debug(input: proc_macro::TokenStream) -> proc_macro::TokenStrea
     let discriminant_impl = discriminant_impl(&data);
     let debug_tag_impl = debug_tag_impl(&data);
     quote! {
-        impl #impl_generics PhenotypeDebug for #ident #ty_generics
+        impl #impl_generics ::phenotype_internal::PhenotypeDebug for #ident #ty_generics
             #where_clause
         {
             #discriminant_impl
@@ -447,6 +842,20 @@ pub fn phenotype_debug(input: proc_macro::TokenStream) -> proc_macro::TokenStrea
     .into()
 }
 
+/// Derives `Frozen` for a struct or enum whose fields are all `Frozen`, so
+/// that a `Peapod` can hand out references to enums that hold it. Enums that
+/// derive `Phenotype` get this already.
+#[proc_macro_derive(Frozen)]
+pub fn frozen(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    let fields = match &ast.data {
+        syn::Data::Struct(data) => data.fields.iter().collect::<Vec<_>>(),
+        syn::Data::Enum(data) => data.variants.iter().flat_map(|var| &var.fields).collect(),
+        syn::Data::Union(data) => data.fields.named.iter().collect(),
+    };
+    frozen::frozen_impl(&ast.ident, &ast.generics, fields.into_iter()).into()
+}
+
 /// Code for the discriminant trait method
 fn discriminant_impl(data: &Condensed) -> proc_macro2::TokenStream {
     let enum_name = &data.name;
@@ -465,6 +874,10 @@ fn discriminant_impl(data: &Condensed) -> proc_macro2::TokenStream {
 
     quote! {
         fn discriminant(&self) -> usize {
+            // Stored whole, there's only one tag
+            if <Self as ::phenotype_internal::Phenotype>::BITS == 0 {
+                return 0;
+            }
             match &self {
                 #(#arms)*
             }
@@ -485,9 +898,14 @@ fn debug_tag_impl(data: &Condensed) -> proc_macro2::TokenStream {
             #tag => #stringified,
         }
     });
+    let enum_name_str = enum_name.to_string();
 
     quote! {
         fn debug_tag(tag: usize) -> &'static str {
+            // Stored whole, the one tag stands for the whole enum
+            if <Self as ::phenotype_internal::Phenotype>::BITS == 0 {
+                return #enum_name_str;
+            }
             match tag {
                 #(#arms)*
                 _ => ::core::panic!("invalid tag")
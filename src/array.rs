@@ -54,7 +54,7 @@ where
     }
 
     // Will overwrite last element
-    pub fn force_push(&mut self, elem: T) {
+    pub fn force_push(&mut self, _elem: T) {
         todo!()
     }
 
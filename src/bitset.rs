@@ -0,0 +1,232 @@
+extern crate alloc;
+use crate::{
+    tag_vec::{TagOrder, TagWord},
+    Peapod, TagPeapod,
+};
+use allocator_api2::alloc::Allocator;
+use bitvec::slice::{IterOnes, IterZeros};
+use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+use phenotype_internal::Phenotype;
+
+// An enum with a one-bit tag, like `Alive`/`Dead`, makes a collection of them a
+// bitset: element `i` is bit `i` of the tags. These work on the tags a word at a
+// time, through bitvec, and never reknit an element.
+//
+// Any bit is a valid one-bit tag, so combining tags can only go wrong when the
+// variants carry data. `Peapod` checks for that, `TagPeapod` can't hold them.
+
+// Both are checked at compile time, so using these on the wrong enum doesn't
+// build rather than panicking
+fn check_bits<T: Phenotype>() {
+    const { assert!(T::BITS == 1, "bitset operations need a one-bit tag") };
+}
+
+fn check_unit<T: Phenotype>() {
+    check_bits::<T>();
+    const {
+        assert!(
+            T::ALL_UNIT,
+            "bitwise operations can't change the variant of elements that carry data"
+        )
+    };
+}
+
+macro_rules! bitset {
+    ($pod:ident) => {
+        impl<T, A, W, O> $pod<T, A, W, O>
+        where
+            T: Phenotype,
+            A: Allocator,
+            W: TagWord,
+            O: TagOrder,
+        {
+            /// Returns the number of elements whose tag is 1, i.e. of the
+            /// second variant.
+            ///
+            /// Doesn't compile if `T::BITS` isn't 1.
+            pub fn count_ones(&self) -> usize {
+                check_bits::<T>();
+                self.tag_bits().count_ones()
+            }
+
+            /// Returns the number of elements whose tag is 0, i.e. of the
+            /// first variant.
+            ///
+            /// Doesn't compile if `T::BITS` isn't 1.
+            pub fn count_zeros(&self) -> usize {
+                check_bits::<T>();
+                self.tag_bits().count_zeros()
+            }
+
+            /// Returns an iterator over the indices of the elements whose tag
+            /// is 1, in order. It skips over whole words of zeros at a time.
+            ///
+            /// Doesn't compile if `T::BITS` isn't 1.
+            pub fn iter_ones(&self) -> IterOnes<'_, W, O> {
+                check_bits::<T>();
+                self.tag_bits().iter_ones()
+            }
+
+            /// Returns an iterator over the indices of the elements whose tag
+            /// is 0, in order.
+            ///
+            /// Doesn't compile if `T::BITS` isn't 1.
+            pub fn iter_zeros(&self) -> IterZeros<'_, W, O> {
+                check_bits::<T>();
+                self.tag_bits().iter_zeros()
+            }
+        }
+
+        bitset!(@op $pod, BitAndAssign, bitand_assign, BitAnd, bitand, &=);
+        bitset!(@op $pod, BitOrAssign, bitor_assign, BitOr, bitor, |=);
+        bitset!(@op $pod, BitXorAssign, bitxor_assign, BitXor, bitxor, ^=);
+
+        /// Flips the tag of every element. Elements are overwritten in place,
+        /// without being dropped.
+        ///
+        /// Doesn't compile if `T::BITS` isn't 1, or if any variant of `T`
+        /// carries data.
+        impl<T, A, W, O> Not for $pod<T, A, W, O>
+        where
+            T: Phenotype,
+            A: Allocator,
+            W: TagWord,
+            O: TagOrder,
+        {
+            type Output = Self;
+
+            fn not(mut self) -> Self {
+                check_unit::<T>();
+                // Inverts the bits in place, the returned reference isn't needed
+                let _ = !self.tag_bits_mut();
+                self
+            }
+        }
+    };
+    (@op $pod:ident, $assign:ident, $assign_fn:ident, $op:ident, $op_fn:ident, $token:tt) => {
+        /// Combines the tag of each element with the tag of the element at the
+        /// same index in `rhs`, a word at a time. Elements are overwritten in
+        /// place, without being dropped.
+        ///
+        /// Doesn't compile if `T::BITS` isn't 1, or if any variant of `T`
+        /// carries data.
+        ///
+        /// ## Panics
+        /// Panics if the two collections have different lengths.
+        impl<T, A, B, W, O> $assign<&$pod<T, B, W, O>> for $pod<T, A, W, O>
+        where
+            T: Phenotype,
+            A: Allocator,
+            B: Allocator,
+            W: TagWord,
+            O: TagOrder,
+        {
+            fn $assign_fn(&mut self, rhs: &$pod<T, B, W, O>) {
+                check_unit::<T>();
+                assert_eq!(
+                    self.len(),
+                    rhs.len(),
+                    "the collections have different lengths"
+                );
+                *self.tag_bits_mut() $token rhs.tag_bits();
+            }
+        }
+
+        /// See the assigning version of this operator.
+        impl<T, A, B, W, O> $op<&$pod<T, B, W, O>> for $pod<T, A, W, O>
+        where
+            T: Phenotype,
+            A: Allocator,
+            B: Allocator,
+            W: TagWord,
+            O: TagOrder,
+        {
+            type Output = Self;
+
+            fn $op_fn(mut self, rhs: &$pod<T, B, W, O>) -> Self {
+                self $token rhs;
+                self
+            }
+        }
+    };
+}
+
+bitset!(Peapod);
+bitset!(TagPeapod);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{peapod, Global, Msb0};
+    use alloc::vec::Vec;
+    use phenotype_macro::Phenotype;
+
+    #[derive(Phenotype, PartialEq, Debug, Clone, Copy)]
+    enum Cell {
+        Dead,
+        Alive,
+    }
+
+    // Alive wherever `alive` says, over more than one word
+    fn cells<P: FromIterator<Cell>>(alive: impl Fn(usize) -> bool) -> P {
+        (0..100)
+            .map(|i| if alive(i) { Cell::Alive } else { Cell::Dead })
+            .collect()
+    }
+
+    #[test]
+    fn counts_and_indices() {
+        let pp: Peapod<Cell> = cells(|i| i % 7 == 0);
+        assert_eq!(pp.count_ones(), 15);
+        assert_eq!(pp.count_zeros(), 85);
+        assert!(pp.iter_ones().eq((0..100).step_by(7)));
+        assert_eq!(pp.iter_zeros().next(), Some(1));
+        assert_eq!(pp.iter_ones().next_back(), Some(98));
+
+        // Reading doesn't care what the variants carry
+        #[derive(Phenotype)]
+        enum Slot {
+            Empty,
+            Full(u32),
+        }
+        let slots = peapod![Slot::Full(1), Slot::Empty, Slot::Full(2)];
+        assert_eq!(slots.count_ones(), 2);
+        assert!(slots.iter_zeros().eq([1]));
+    }
+
+    #[test]
+    fn bitwise() {
+        let a: TagPeapod<Cell, Global, u8, Msb0> = cells(|i| i % 2 == 0);
+        let b: TagPeapod<Cell, Global, u8, Msb0> = cells(|i| i % 3 == 0);
+
+        let and = a.clone() & &b;
+        assert!(and.iter_ones().eq((0..100).step_by(6)));
+        let or = a.clone() | &b;
+        assert_eq!(or.count_ones(), 67);
+        let mut xor = a.clone();
+        xor ^= &b;
+        assert_eq!(xor.count_ones(), 50 + 34 - 2 * 17);
+        let not = !a;
+        assert!(not.iter_ones().eq((1..100).step_by(2)));
+        assert_eq!(*not.get(1).unwrap(), Cell::Alive);
+    }
+
+    #[test]
+    fn bitwise_leaves_cruft_alone() {
+        let mut pp: Peapod<Cell> = cells(|_| false);
+        pp.pop();
+        let pp = !pp;
+        assert_eq!(pp.len(), 99);
+        assert_eq!(pp.count_ones(), 99);
+        assert_eq!(Vec::from(pp), [Cell::Alive; 99]);
+    }
+
+    #[test]
+    #[should_panic(expected = "different lengths")]
+    fn lengths_must_match() {
+        let mut a: Peapod<Cell> = cells(|_| true);
+        a.pop();
+        let b: Peapod<Cell> = cells(|_| true);
+        a &= &b;
+    }
+}
@@ -0,0 +1,1169 @@
+extern crate alloc;
+use crate::{PeaRef, Peapod};
+use alloc::{vec, vec::Vec};
+use core::{
+    borrow::Borrow,
+    cmp,
+    fmt::{self, Debug},
+    mem,
+    ops::{Bound, RangeBounds},
+};
+use phenotype_internal::{Frozen, Phenotype};
+
+// The most entries a leaf can hold before it gets split in two
+const LEAF_CAPACITY: usize = 64;
+
+// The most children an internal node can have before it gets split in two
+const BRANCH_CAPACITY: usize = 16;
+
+// Nodes other than the root are merged with a neighbor once they're less than a
+// quarter full. If the merged node is then too big, it's split in half again,
+// which amounts to moving entries over from the neighbor.
+const LEAF_MIN: usize = LEAF_CAPACITY / 4;
+const BRANCH_MIN: usize = BRANCH_CAPACITY / 4;
+
+// Entries are found by rank, their index in key order. Every node knows how many
+// entries are below it, so a rank leads straight down to its leaf, and ranges of
+// entries are just ranges of ranks.
+
+/// A sorted run of entries. The values are stored `cleave`d in a [`Peapod`].
+struct Leaf<K, V>
+where
+    V: Phenotype,
+{
+    keys: Vec<K>,
+    values: Peapod<V>,
+    // How many values of each variant this leaf holds, indexed by tag
+    summary: Vec<usize>,
+}
+
+impl<K, V> Leaf<K, V>
+where
+    V: Phenotype,
+{
+    fn new() -> Self {
+        Self {
+            keys: Vec::with_capacity(LEAF_CAPACITY),
+            values: Peapod::with_capacity(LEAF_CAPACITY),
+            summary: vec![0; V::NUM_VARIANTS],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    // **Note**: leaves in a map are never empty
+    fn last_key(&self) -> &K {
+        &self.keys[self.keys.len() - 1]
+    }
+
+    fn insert(&mut self, index: usize, key: K, value: V) {
+        self.keys.insert(index, key);
+        self.values.insert(index, value);
+        self.summary[self.values.get_variant(index)] += 1;
+    }
+
+    fn remove(&mut self, index: usize) -> (K, V) {
+        self.summary[self.values.get_variant(index)] -= 1;
+        (self.keys.remove(index), self.values.remove(index))
+    }
+
+    fn replace(&mut self, index: usize, value: V) -> V {
+        self.summary[self.values.get_variant(index)] -= 1;
+        let old = self.values.remove(index);
+        self.values.insert(index, value);
+        self.summary[self.values.get_variant(index)] += 1;
+        old
+    }
+
+    fn split_off(&mut self, at: usize) -> Self {
+        let keys = self.keys.split_off(at);
+        let values = self.values.split_off(at);
+        let mut summary = vec![0; V::NUM_VARIANTS];
+        for i in 0..values.len() {
+            summary[values.get_variant(i)] += 1;
+        }
+        for (mine, theirs) in self.summary.iter_mut().zip(&summary) {
+            *mine -= theirs;
+        }
+        Self {
+            keys,
+            values,
+            summary,
+        }
+    }
+
+    fn append(&mut self, other: Self) {
+        let Self {
+            mut keys,
+            mut values,
+            summary,
+        } = other;
+        self.keys.append(&mut keys);
+        self.values.append(&mut values);
+        for (mine, theirs) in self.summary.iter_mut().zip(&summary) {
+            *mine += theirs;
+        }
+    }
+}
+
+/// A node with children, all at the same depth.
+///
+/// It doesn't store any keys, so `K` doesn't have to be `Clone`: searches compare
+/// against the last key of each child instead, found by following the child's
+/// rightmost edge down to a leaf.
+struct Internal<K, V>
+where
+    V: Phenotype,
+{
+    children: Vec<Node<K, V>>,
+    // The number of entries in the subtree
+    len: usize,
+    // How many values of each variant the subtree holds, indexed by tag
+    summary: Vec<usize>,
+}
+
+impl<K, V> Internal<K, V>
+where
+    V: Phenotype,
+{
+    fn new(children: Vec<Node<K, V>>) -> Self {
+        let mut node = Self {
+            children,
+            len: 0,
+            summary: vec![0; V::NUM_VARIANTS],
+        };
+        node.update();
+        node
+    }
+
+    // Recounts the entries and variants after the children change
+    fn update(&mut self) {
+        self.len = 0;
+        self.summary.fill(0);
+        for child in &self.children {
+            self.len += child.len();
+            for (mine, theirs) in self.summary.iter_mut().zip(child.summary()) {
+                *mine += theirs;
+            }
+        }
+    }
+
+    // Returns the child holding the entry at `rank`, and the rank its entries
+    // start at.
+    // **Note**: rank must be < len
+    fn child_at(&self, rank: usize) -> (usize, usize) {
+        let mut start = 0;
+        for (i, child) in self.children.iter().enumerate() {
+            if rank < start + child.len() {
+                return (i, start);
+            }
+            start += child.len();
+        }
+        unreachable!("rank {rank} is out of bounds for a subtree of {}", self.len)
+    }
+
+    // Merges child `i` with a neighbor if it's gotten too small. Doesn't change
+    // the entries in the subtree, so there's nothing to update
+    fn rebalance(&mut self, i: usize) {
+        if self.children.len() < 2 || !self.children[i].is_underfull() {
+            return;
+        }
+        let left = if i + 1 < self.children.len() {
+            i
+        } else {
+            i - 1
+        };
+        let right = self.children.remove(left + 1);
+        self.children[left].append(right);
+        if let Some(split) = self.children[left].split_if_full() {
+            self.children.insert(left + 1, split);
+        }
+    }
+}
+
+enum Node<K, V>
+where
+    V: Phenotype,
+{
+    Leaf(Leaf<K, V>),
+    Internal(Internal<K, V>),
+}
+
+impl<K, V> Node<K, V>
+where
+    V: Phenotype,
+{
+    fn len(&self) -> usize {
+        match self {
+            Node::Leaf(leaf) => leaf.len(),
+            Node::Internal(node) => node.len,
+        }
+    }
+
+    fn summary(&self) -> &[usize] {
+        match self {
+            Node::Leaf(leaf) => &leaf.summary,
+            Node::Internal(node) => &node.summary,
+        }
+    }
+
+    fn has_tag(&self, tag: usize) -> bool {
+        self.summary().get(tag).is_some_and(|&count| count > 0)
+    }
+
+    fn is_underfull(&self) -> bool {
+        match self {
+            Node::Leaf(leaf) => leaf.len() < LEAF_MIN,
+            Node::Internal(node) => node.children.len() < BRANCH_MIN,
+        }
+    }
+
+    // **Note**: nodes in a map are never empty
+    fn last_key(&self) -> &K {
+        let mut node = self;
+        loop {
+            match node {
+                Node::Leaf(leaf) => return leaf.last_key(),
+                Node::Internal(internal) => node = &internal.children[internal.children.len() - 1],
+            }
+        }
+    }
+
+    // Returns the leaf holding the entry at `rank`, and the rank its entries
+    // start at.
+    // **Note**: rank must be < len
+    fn leaf_at(&self, rank: usize) -> (&Leaf<K, V>, usize) {
+        let mut node = self;
+        let mut start = 0;
+        loop {
+            match node {
+                Node::Leaf(leaf) => return (leaf, start),
+                Node::Internal(internal) => {
+                    let (i, child_start) = internal.child_at(rank - start);
+                    start += child_start;
+                    node = &internal.children[i];
+                }
+            }
+        }
+    }
+
+    // Moves the entries of `other`, which must be at the same depth and come
+    // after this node's, to the end of this node
+    fn append(&mut self, other: Self) {
+        match (self, other) {
+            (Node::Leaf(leaf), Node::Leaf(other)) => leaf.append(other),
+            (Node::Internal(node), Node::Internal(other)) => {
+                node.children.extend(other.children);
+                node.update();
+            }
+            _ => unreachable!("neighbors are at the same depth"),
+        }
+    }
+
+    // Splits the node in half if it holds more than it can
+    fn split_if_full(&mut self) -> Option<Self> {
+        match self {
+            Node::Leaf(leaf) if leaf.len() > LEAF_CAPACITY => {
+                Some(Node::Leaf(leaf.split_off(leaf.len() / 2)))
+            }
+            Node::Internal(node) if node.children.len() > BRANCH_CAPACITY => {
+                let right = node.children.split_off(node.children.len() / 2);
+                node.update();
+                Some(Node::Internal(Internal::new(right)))
+            }
+            _ => None,
+        }
+    }
+
+    // **Note**: rank must be < len
+    fn remove_at(&mut self, rank: usize) -> (K, V) {
+        match self {
+            Node::Leaf(leaf) => leaf.remove(rank),
+            Node::Internal(node) => {
+                let (i, start) = node.child_at(rank);
+                let entry = node.children[i].remove_at(rank - start);
+                node.rebalance(i);
+                node.update();
+                entry
+            }
+        }
+    }
+
+    // Moves the entries from `rank` onwards into a new node at the same depth.
+    // Nodes along the cut can be left too small, see `fix_edge`.
+    // **Note**: rank must be > 0 and < len, so neither half is empty
+    fn split_off(&mut self, rank: usize) -> Self {
+        match self {
+            Node::Leaf(leaf) => Node::Leaf(leaf.split_off(rank)),
+            Node::Internal(node) => {
+                let (i, start) = node.child_at(rank);
+                let right = if rank == start {
+                    // The cut falls between two children
+                    node.children.split_off(i)
+                } else {
+                    let cut = node.children[i].split_off(rank - start);
+                    let mut right = node.children.split_off(i + 1);
+                    right.insert(0, cut);
+                    right
+                };
+                node.update();
+                Node::Internal(Internal::new(right))
+            }
+        }
+    }
+
+    // Merges away the nodes that are too small along the last (or first) edge of
+    // the subtree, top down, like after a split
+    fn fix_edge(&mut self, last: bool) {
+        if let Node::Internal(node) = self {
+            let edge = |node: &Internal<K, V>| if last { node.children.len() - 1 } else { 0 };
+            node.rebalance(edge(node));
+            let i = edge(node);
+            node.children[i].fix_edge(last);
+        }
+    }
+
+    // Returns how many values with ranks in `from..to` are the variant `tag`.
+    // Subtrees entirely in the range are counted with their summaries, and
+    // subtrees without the variant are skipped
+    fn count_tag(&self, from: usize, to: usize, tag: usize) -> usize {
+        if from >= to || !self.has_tag(tag) {
+            return 0;
+        }
+        if from == 0 && to == self.len() {
+            return self.summary()[tag];
+        }
+        match self {
+            Node::Leaf(leaf) => (from..to)
+                .filter(|&index| leaf.values.get_variant(index) == tag)
+                .count(),
+            Node::Internal(node) => {
+                let mut count = 0;
+                let mut start = 0;
+                for child in &node.children {
+                    let end = start + child.len();
+                    if end > from && start < to {
+                        count += child.count_tag(
+                            from.saturating_sub(start),
+                            cmp::min(to, end) - start,
+                            tag,
+                        );
+                    }
+                    start = end;
+                }
+                count
+            }
+        }
+    }
+
+    // Returns the first value with a rank in `from..to` that is the variant
+    // `tag`, as its rank, its leaf and its index in the leaf. Subtrees without
+    // the variant are skipped
+    fn find_tag(&self, from: usize, to: usize, tag: usize) -> Option<(usize, &Leaf<K, V>, usize)> {
+        if from >= to || !self.has_tag(tag) {
+            return None;
+        }
+        match self {
+            Node::Leaf(leaf) => (from..to)
+                .find(|&index| leaf.values.get_variant(index) == tag)
+                .map(|index| (index, leaf, index)),
+            Node::Internal(node) => {
+                let mut start = 0;
+                for child in &node.children {
+                    let end = start + child.len();
+                    if end > from && start < to {
+                        let found = child.find_tag(
+                            from.saturating_sub(start),
+                            cmp::min(to, end) - start,
+                            tag,
+                        );
+                        if let Some((rank, leaf, index)) = found {
+                            return Some((start + rank, leaf, index));
+                        }
+                    }
+                    if end >= to {
+                        break;
+                    }
+                    start = end;
+                }
+                None
+            }
+        }
+    }
+}
+
+impl<K, V> Node<K, V>
+where
+    K: Ord,
+    V: Phenotype,
+{
+    // Returns the rank of the first entry whose key is >= `key`, or > `key` if
+    // `past_equal` is true
+    fn seek<Q>(&self, key: &Q, past_equal: bool) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let before = |k: &K| match k.borrow().cmp(key) {
+            cmp::Ordering::Less => true,
+            cmp::Ordering::Equal => past_equal,
+            cmp::Ordering::Greater => false,
+        };
+
+        let mut node = self;
+        let mut rank = 0;
+        loop {
+            match node {
+                Node::Leaf(leaf) => return rank + leaf.keys.partition_point(|k| before(k)),
+                Node::Internal(internal) => {
+                    let children = &internal.children;
+                    let i = children.partition_point(|child| before(child.last_key()));
+                    rank += children[..i].iter().map(Node::len).sum::<usize>();
+                    match children.get(i) {
+                        Some(child) => node = child,
+                        None => return rank,
+                    }
+                }
+            }
+        }
+    }
+
+    // Inserts the entry, or replaces the value if `key` is already in the map.
+    // The node can be left too big, see `split_if_full`
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self {
+            Node::Leaf(leaf) => match leaf.keys.binary_search(&key) {
+                Ok(index) => Some(leaf.replace(index, value)),
+                Err(index) => {
+                    leaf.insert(index, key, value);
+                    None
+                }
+            },
+            Node::Internal(node) => {
+                // Keys larger than every key in the subtree go in the last child
+                let i = node
+                    .children
+                    .partition_point(|child| child.last_key() < &key)
+                    .min(node.children.len() - 1);
+                let old = node.children[i].insert(key, value);
+                if let Some(split) = node.children[i].split_if_full() {
+                    node.children.insert(i + 1, split);
+                }
+                node.update();
+                old
+            }
+        }
+    }
+}
+
+/// An ordered map from keys to `enum`s that implement [`Phenotype`], based on a
+/// B+ tree.
+///
+/// Entries live in sorted leaves of up to 64 entries, under internal nodes of up
+/// to 16 children. Like in a [`Peapod`], the values in each leaf are stored as a
+/// tag bit-field plus an array of `V::Value`s, not as full `V`s. Every node also
+/// keeps a summary of how many values of each variant its subtree holds, so
+/// queries for a particular variant, like [`PeaBTreeMap::range_contains_tag`],
+/// skip whole subtrees that don't have it.
+///
+/// Tags are the ones `Phenotype` uses internally, which can be retrieved with
+/// [`PhenotypeDebug::discriminant`](crate::PhenotypeDebug::discriminant).
+///
+/// Since values aren't stored as `V`s, lookups return [`PeaRef`]s instead of `&V`s.
+pub struct PeaBTreeMap<K, V>
+where
+    V: Phenotype,
+{
+    root: Option<Node<K, V>>,
+}
+
+impl<K, V> PeaBTreeMap<K, V>
+where
+    V: Phenotype,
+{
+    /// Create a new, empty `PeaBTreeMap`. This does not allocate.
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, Node::len)
+    }
+
+    /// Returns whether the map is empty (it contains no entries).
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Removes all entries from the map.
+    pub fn clear(&mut self) {
+        self.root = None;
+    }
+
+    /// Returns the entry with the smallest key, or `None` if the map is empty.
+    pub fn first_key_value(&self) -> Option<(&K, PeaRef<'_, V>)> {
+        self.iter().next()
+    }
+
+    /// Returns the entry with the largest key, or `None` if the map is empty.
+    pub fn last_key_value(&self) -> Option<(&K, PeaRef<'_, V>)> {
+        self.iter().next_back()
+    }
+
+    /// Removes and returns the entry with the smallest key, or `None` if the map
+    /// is empty.
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(self.remove_at(0))
+    }
+
+    /// Removes and returns the entry with the largest key, or `None` if the map
+    /// is empty.
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        let last = self.len().checked_sub(1)?;
+        Some(self.remove_at(last))
+    }
+
+    /// Returns an iterator over the entries of the map, in key order.
+    pub fn iter(&self) -> Range<'_, K, V> {
+        self.range_of_ranks(0, self.len())
+    }
+
+    fn range_of_ranks(&self, front: usize, back: usize) -> Range<'_, K, V> {
+        Range {
+            root: self.root.as_ref(),
+            front,
+            back,
+            front_leaf: None,
+            back_leaf: None,
+        }
+    }
+
+    // **Note**: rank must be < len
+    fn remove_at(&mut self, rank: usize) -> (K, V) {
+        let root = self.root.as_mut().expect("the map is not empty");
+        let entry = root.remove_at(rank);
+        if root.len() == 0 {
+            self.root = None;
+        }
+        self.shrink();
+        entry
+    }
+
+    // Replaces a root with only one child by that child, until it has more
+    fn shrink(&mut self) {
+        while let Some(Node::Internal(root)) = &mut self.root {
+            if root.children.len() > 1 {
+                break;
+            }
+            self.root = root.children.pop();
+        }
+    }
+
+    // Restores the invariants along the last (or first) edge after a split
+    fn fix_edge(&mut self, last: bool) {
+        self.shrink();
+        if let Some(root) = &mut self.root {
+            root.fix_edge(last);
+        }
+        self.shrink();
+    }
+}
+
+impl<K, V> PeaBTreeMap<K, V>
+where
+    K: Ord,
+    V: Phenotype,
+{
+    // Returns the rank of the first entry whose key is >= `key`, or > `key` if
+    // `past_equal` is true
+    fn seek<Q>(&self, key: &Q, past_equal: bool) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.root
+            .as_ref()
+            .map_or(0, |root| root.seek(key, past_equal))
+    }
+
+    // Turns range bounds into the rank of the first entry in the range and the
+    // rank of the first entry past the range.
+    fn bounds<Q, R>(&self, range: R) -> (usize, usize)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        match (range.start_bound(), range.end_bound()) {
+            (Bound::Excluded(start), Bound::Excluded(end)) if start == end => {
+                panic!("range start and end are equal and excluded in PeaBTreeMap")
+            }
+            (
+                Bound::Included(start) | Bound::Excluded(start),
+                Bound::Included(end) | Bound::Excluded(end),
+            ) if start > end => panic!("range start is greater than range end in PeaBTreeMap"),
+            _ => {}
+        }
+
+        let front = match range.start_bound() {
+            Bound::Included(start) => self.seek(start, false),
+            Bound::Excluded(start) => self.seek(start, true),
+            Bound::Unbounded => 0,
+        };
+        let back = match range.end_bound() {
+            Bound::Included(end) => self.seek(end, true),
+            Bound::Excluded(end) => self.seek(end, false),
+            Bound::Unbounded => self.len(),
+        };
+        (front, back)
+    }
+
+    // Returns the rank of `key`, and the leaf and index it's stored at
+    fn find<Q>(&self, key: &Q) -> Option<(usize, &Leaf<K, V>, usize)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let root = self.root.as_ref()?;
+        let rank = root.seek(key, false);
+        if rank == root.len() {
+            return None;
+        }
+        let (leaf, start) = root.leaf_at(rank);
+        let index = rank - start;
+        (leaf.keys[index].borrow() == key).then_some((rank, leaf, index))
+    }
+
+    /// Returns the value corresponding to `key`, or `None` if the map doesn't
+    /// contain it.
+    pub fn get<Q>(&self, key: &Q) -> Option<PeaRef<'_, V>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find(key)
+            .map(|(_, leaf, index)| leaf.values.view(index))
+    }
+
+    /// Returns whether the map contains a value for `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    /// Inserts a key-value pair into the map. If the map already had a value for
+    /// `key`, it is replaced and the old value is returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let root = self.root.get_or_insert_with(|| Node::Leaf(Leaf::new()));
+        let old = root.insert(key, value);
+        if let Some(split) = root.split_if_full() {
+            // Grow a level
+            let left = self.root.take().expect("the map is not empty");
+            self.root = Some(Node::Internal(Internal::new(vec![left, split])));
+        }
+        old
+    }
+
+    /// Removes `key` from the map, returning its value if it was in the map.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (rank, _, _) = self.find(key)?;
+        Some(self.remove_at(rank).1)
+    }
+
+    /// Splits the map in two at `key`. Returns everything after `key`,
+    /// including `key` itself.
+    pub fn split_off<Q>(&mut self, key: &Q) -> Self
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let rank = self.seek(key, false);
+        if rank == 0 {
+            return mem::take(self);
+        }
+        let Some(root) = self.root.as_mut().filter(|root| rank < root.len()) else {
+            return Self::new();
+        };
+
+        // Only the nodes along the cut change, so this takes O(log n)
+        let mut right = Self {
+            root: Some(root.split_off(rank)),
+        };
+        self.fix_edge(true);
+        right.fix_edge(false);
+        right
+    }
+
+    /// Returns an iterator over the entries with keys in `range`, in key order.
+    ///
+    /// ## Panics
+    /// Like `BTreeMap::range`, panics if the start of the range is greater than the
+    /// end, or if both ends are the same and excluded.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (front, back) = self.bounds(range);
+        self.range_of_ranks(front, back)
+    }
+
+    /// Returns an iterator over the entries with keys in `range` whose value is
+    /// the variant `tag`, in key order. Subtrees without that variant are
+    /// skipped.
+    ///
+    /// ## Panics
+    /// Panics in the same cases as [`PeaBTreeMap::range`].
+    pub fn range_with_tag<Q, R>(&self, range: R, tag: usize) -> RangeWithTag<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        RangeWithTag {
+            range: self.range(range),
+            tag,
+        }
+    }
+
+    /// Returns whether any value with a key in `range` is the variant `tag`.
+    /// Subtrees without that variant are skipped.
+    ///
+    /// ## Panics
+    /// Panics in the same cases as [`PeaBTreeMap::range`].
+    pub fn range_contains_tag<Q, R>(&self, range: R, tag: usize) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        self.range_with_tag(range, tag).next().is_some()
+    }
+
+    /// Returns how many values with a key in `range` are the variant `tag`.
+    /// Subtrees entirely in the range are counted using their summaries, so this
+    /// only looks at individual values in the two leaves at the ends of the range.
+    ///
+    /// ## Panics
+    /// Panics in the same cases as [`PeaBTreeMap::range`].
+    pub fn range_count_tag<Q, R>(&self, range: R, tag: usize) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (front, back) = self.bounds(range);
+        self.root
+            .as_ref()
+            .map_or(0, |root| root.count_tag(front, back, tag))
+    }
+}
+
+/// An iterator over a range of entries of a [`PeaBTreeMap`].
+pub struct Range<'a, K, V>
+where
+    V: Phenotype,
+{
+    root: Option<&'a Node<K, V>>,
+    // Rank of the next entry to yield from the front
+    front: usize,
+    // Rank one past the next entry to yield from the back
+    back: usize,
+    // The last leaf each end looked in, and the rank its entries start at, so
+    // stepping through a leaf doesn't go through the tree every time
+    front_leaf: Option<(&'a Leaf<K, V>, usize)>,
+    back_leaf: Option<(&'a Leaf<K, V>, usize)>,
+}
+
+// Returns the entry at `rank`, looking in `cache` first and updating it if the
+// entry is in another leaf.
+// **Note**: rank must be < len
+fn entry_at<'a, K, V>(
+    root: &'a Node<K, V>,
+    cache: &mut Option<(&'a Leaf<K, V>, usize)>,
+    rank: usize,
+) -> (&'a K, PeaRef<'a, V>)
+where
+    V: Phenotype,
+{
+    let (leaf, start) = match *cache {
+        Some((leaf, start)) if (start..start + leaf.len()).contains(&rank) => (leaf, start),
+        _ => *cache.insert(root.leaf_at(rank)),
+    };
+    let index = rank - start;
+    (&leaf.keys[index], leaf.values.view(index))
+}
+
+impl<'a, K, V> Iterator for Range<'a, K, V>
+where
+    V: Phenotype,
+{
+    type Item = (&'a K, PeaRef<'a, V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Are we done iterating?
+        if self.front == self.back {
+            return None;
+        }
+
+        let entry = entry_at(self.root?, &mut self.front_leaf, self.front);
+        self.front += 1;
+        Some(entry)
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Range<'_, K, V>
+where
+    V: Phenotype,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        // Are we done iterating?
+        if self.front == self.back {
+            return None;
+        }
+
+        self.back -= 1;
+        Some(entry_at(self.root?, &mut self.back_leaf, self.back))
+    }
+}
+
+/// An iterator over the entries in a range of a [`PeaBTreeMap`] that hold
+/// a particular variant. Created by [`PeaBTreeMap::range_with_tag`].
+pub struct RangeWithTag<'a, K, V>
+where
+    V: Phenotype,
+{
+    range: Range<'a, K, V>,
+    tag: usize,
+}
+
+impl<'a, K, V> Iterator for RangeWithTag<'a, K, V>
+where
+    V: Phenotype,
+{
+    type Item = (&'a K, PeaRef<'a, V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let range = &mut self.range;
+        let found = range
+            .root
+            .and_then(|root| root.find_tag(range.front, range.back, self.tag));
+        match found {
+            Some((rank, leaf, index)) => {
+                range.front = rank + 1;
+                Some((&leaf.keys[index], leaf.values.view(index)))
+            }
+            None => {
+                range.front = range.back;
+                None
+            }
+        }
+    }
+}
+
+impl<K, V> Default for PeaBTreeMap<K, V>
+where
+    V: Phenotype,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Debug for PeaBTreeMap<K, V>
+where
+    K: Debug,
+    V: Phenotype + Debug + Frozen,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> Extend<(K, V)> for PeaBTreeMap<K, V>
+where
+    K: Ord,
+    V: Phenotype,
+{
+    fn extend<A: IntoIterator<Item = (K, V)>>(&mut self, iter: A) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for PeaBTreeMap<K, V>
+where
+    K: Ord,
+    V: Phenotype,
+{
+    fn from_iter<A: IntoIterator<Item = (K, V)>>(iter: A) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a PeaBTreeMap<K, V>
+where
+    V: Phenotype,
+{
+    type Item = (&'a K, PeaRef<'a, V>);
+
+    type IntoIter = Range<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PhenotypeDebug;
+    use alloc::collections::BTreeMap;
+    use phenotype_macro::Phenotype;
+
+    #[derive(Phenotype, PhenotypeDebug, PartialEq, Debug)]
+    enum Status {
+        Ok(u32),
+        Failed { code: u16 },
+        Pending,
+    }
+
+    // Every 100th key is `Failed`, so most leaves don't contain one
+    fn status_map() -> PeaBTreeMap<usize, Status> {
+        (0..1000)
+            .map(|i| {
+                let status = if i % 100 == 50 {
+                    Status::Failed { code: i as u16 }
+                } else if i % 2 == 0 {
+                    Status::Ok(i as u32)
+                } else {
+                    Status::Pending
+                };
+                (i, status)
+            })
+            .collect()
+    }
+
+    // Checks the shape of the tree and the counts every node keeps, and returns
+    // its height
+    fn check<K: Ord, V: Phenotype>(map: &PeaBTreeMap<K, V>) -> usize {
+        fn check_node<K: Ord, V: Phenotype>(node: &Node<K, V>, root: bool) -> usize {
+            assert!(root || !node.is_underfull());
+            let mut summary = vec![0; V::NUM_VARIANTS];
+            let height = match node {
+                Node::Leaf(leaf) => {
+                    assert!(leaf.len() <= LEAF_CAPACITY);
+                    assert!(leaf.keys.windows(2).all(|pair| pair[0] < pair[1]));
+                    for i in 0..leaf.values.len() {
+                        summary[leaf.values.get_variant(i)] += 1;
+                    }
+                    1
+                }
+                Node::Internal(internal) => {
+                    assert!((2..=BRANCH_CAPACITY).contains(&internal.children.len()));
+                    let heights: Vec<_> = internal
+                        .children
+                        .iter()
+                        .map(|child| check_node(child, false))
+                        .collect();
+                    assert!(heights.iter().all(|&height| height == heights[0]));
+                    for child in &internal.children {
+                        for (mine, theirs) in summary.iter_mut().zip(child.summary()) {
+                            *mine += theirs;
+                        }
+                    }
+                    let len: usize = internal.children.iter().map(Node::len).sum();
+                    assert_eq!(internal.len, len);
+                    heights[0] + 1
+                }
+            };
+            assert_eq!(node.summary(), summary);
+            height
+        }
+
+        assert!(map.iter().zip(map.iter().skip(1)).all(|(a, b)| a.0 < b.0));
+        map.root.as_ref().map_or(0, |root| check_node(root, true))
+    }
+
+    fn status(i: usize) -> Status {
+        match i % 3 {
+            0 => Status::Ok(i as u32),
+            1 => Status::Failed { code: i as u16 },
+            _ => Status::Pending,
+        }
+    }
+
+    #[test]
+    fn insert_get_remove() {
+        let mut map = PeaBTreeMap::new();
+        assert_eq!(map.insert(3, Status::Pending), None);
+        assert_eq!(map.insert(1, Status::Ok(1)), None);
+        assert_eq!(map.insert(3, Status::Ok(3)), Some(Status::Pending));
+        assert_eq!(map.len(), 2);
+        assert_eq!(*map.get(&3).unwrap(), Status::Ok(3));
+        assert!(map.get(&2).is_none());
+        assert_eq!(map.remove(&1), Some(Status::Ok(1)));
+        assert_eq!(map.remove(&1), None);
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn stays_sorted_across_leaves() {
+        let mut map = PeaBTreeMap::new();
+        // Insert out of order so leaves get split in the middle
+        for i in (0..500).rev().chain(500..1000) {
+            map.insert(i * 7 % 1000, Status::Ok(i as u32));
+        }
+        assert_eq!(map.len(), 1000);
+        assert!(map.iter().map(|(k, _)| *k).eq(0..1000));
+        assert!(map.iter().rev().map(|(k, _)| *k).eq((0..1000).rev()));
+    }
+
+    #[test]
+    fn range_bounds() {
+        let map = status_map();
+        assert!(map.range(100..200).map(|(k, _)| *k).eq(100..200));
+        assert!(map.range(..=5).map(|(k, _)| *k).eq(0..=5));
+        assert!(map.range(995..).map(|(k, _)| *k).eq(995..1000));
+        assert!(map
+            .range((Bound::Excluded(10), Bound::Included(20)))
+            .map(|(k, _)| *k)
+            .eq(11..=20));
+        assert!(map.range(2000..3000).next().is_none());
+        assert!(map
+            .range(300..320)
+            .rev()
+            .map(|(k, _)| *k)
+            .eq((300..320).rev()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn range_backwards_panics() {
+        status_map().range((Bound::Included(200), Bound::Excluded(100)));
+    }
+
+    #[test]
+    fn tag_queries() {
+        let map = status_map();
+        let failed = Status::Failed { code: 0 }.discriminant();
+        assert!(map.range_contains_tag(100..200, failed));
+        assert!(!map.range_contains_tag(151..250, failed));
+        assert_eq!(map.range_count_tag(.., failed), 10);
+        assert_eq!(map.range_count_tag(0..=150, failed), 2);
+        assert!(map
+            .range_with_tag(.., failed)
+            .map(|(k, _)| *k)
+            .eq((50..1000).step_by(100)));
+    }
+
+    #[test]
+    fn first_last_and_pop() {
+        let mut map = status_map();
+        assert_eq!(*map.first_key_value().unwrap().0, 0);
+        assert_eq!(*map.last_key_value().unwrap().0, 999);
+        assert_eq!(map.pop_first(), Some((0, Status::Ok(0))));
+        assert_eq!(map.pop_last(), Some((999, Status::Pending)));
+        assert_eq!(map.len(), 998);
+        while map.pop_first().is_some() {}
+        assert!(map.is_empty());
+        assert!(map.first_key_value().is_none());
+    }
+
+    #[test]
+    fn split_off_mid_leaf() {
+        let mut map = status_map();
+        let right = map.split_off(&333);
+        assert_eq!(map.len(), 333);
+        assert_eq!(right.len(), 667);
+        assert!(map.iter().map(|(k, _)| *k).eq(0..333));
+        assert!(right.iter().map(|(k, _)| *k).eq(333..1000));
+        let failed = Status::Failed { code: 0 }.discriminant();
+        assert_eq!(map.range_count_tag(.., failed), 3);
+        assert_eq!(right.range_count_tag(.., failed), 7);
+    }
+
+    #[test]
+    fn many_levels_match_btreemap() {
+        let mut map = PeaBTreeMap::new();
+        let mut expected = BTreeMap::new();
+        // Multiplying by a number coprime to 5000 visits every key, out of order
+        for i in (0..5000).map(|i| i * 2003 % 5000) {
+            assert_eq!(map.insert(i, status(i)), expected.insert(i, status(i)));
+        }
+        // 5000 entries need more than one level of internal nodes
+        assert!(check(&map) >= 3);
+        assert_eq!(map.insert(7, Status::Pending), Some(status(7)));
+        expected.insert(7, Status::Pending);
+
+        for (n, i) in (0..5000).map(|i| i * 3001 % 5000).enumerate().take(4500) {
+            assert_eq!(map.remove(&i), expected.remove(&i));
+            if n % 500 == 0 {
+                check(&map);
+            }
+        }
+        check(&map);
+        assert_eq!(map.len(), expected.len());
+        assert!(map
+            .iter()
+            .map(|(k, v)| (*k, v.discriminant()))
+            .eq(expected.iter().map(|(k, v)| (*k, v.discriminant()))));
+
+        while map.pop_last().is_some() {}
+        assert!(map.is_empty());
+        assert_eq!(check(&map), 0);
+    }
+
+    #[test]
+    fn tag_queries_across_levels() {
+        let map: PeaBTreeMap<_, _> = (0..5000).map(|i| (i, status(i))).collect();
+        let failed = Status::Failed { code: 0 }.discriminant();
+        for (start, end) in [(0, 5000), (1, 4999), (64, 128), (100, 3000), (2500, 2501)] {
+            let expected = (start..end).filter(|i| i % 3 == 1);
+            assert_eq!(
+                map.range_count_tag(start..end, failed),
+                expected.clone().count()
+            );
+            assert!(map
+                .range_with_tag(start..end, failed)
+                .map(|(k, _)| *k)
+                .eq(expected));
+        }
+        assert!(!map.range_contains_tag(2501..2503, failed));
+    }
+
+    #[test]
+    fn split_off_everywhere() {
+        for at in [0, 1, 63, 64, 65, 1024, 2500, 4999, 5000, 6000] {
+            let mut map: PeaBTreeMap<_, _> = (0..5000).map(|i| (i, status(i))).collect();
+            let right = map.split_off(&at);
+            check(&map);
+            check(&right);
+            let at = at.min(5000);
+            assert!(map.iter().map(|(k, _)| *k).eq(0..at));
+            assert!(right.iter().map(|(k, _)| *k).eq(at..5000));
+            let failed = Status::Failed { code: 0 }.discriminant();
+            assert_eq!(
+                map.range_count_tag(.., failed),
+                (0..at).filter(|i| i % 3 == 1).count()
+            );
+        }
+    }
+}
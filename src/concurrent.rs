@@ -0,0 +1,442 @@
+extern crate alloc;
+use crate::PeaRef;
+use alloc::boxed::Box;
+use core::{
+    cell::UnsafeCell,
+    fmt::{self, Debug},
+    hint,
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+use phenotype_internal::{Frozen, Phenotype};
+
+const WORD_BITS: usize = usize::BITS as usize;
+
+// The first chunk holds 2^FIRST_SHIFT elements, and each chunk after that holds
+// twice as many as the one before, so there's never a need for more than this
+// many chunks
+const FIRST_SHIFT: u32 = 5;
+const CHUNKS: usize = (usize::BITS - FIRST_SHIFT) as usize;
+
+/// An append-only collection of `enum`s that can be read from many threads
+/// while another thread pushes to it, for example from behind an `Arc`.
+///
+/// Elements are stored `cleave`d like in a [`Peapod`](crate::Peapod), but in
+/// chunks that never move once they're allocated, so references handed out by
+/// [`get`](ConcurrentPeapod::get) and [`iter`](ConcurrentPeapod::iter) stay
+/// valid while elements are being pushed. Pushes are serialized by a spin
+/// lock, so there is only ever one writer at a time, and readers never block.
+///
+/// Elements can't be removed or changed once pushed. To get them back, drop
+/// the collection or collect them into something else first.
+///
+/// Like any [`PeaRef`], the references are to copies, so elements have to be
+/// [`Frozen`] to be read. Otherwise every thread would lock its own copy of a
+/// `Mutex` like this one:
+///
+/// ```compile_fail
+/// use peapod::{ConcurrentPeapod, Phenotype};
+/// use std::sync::Mutex;
+///
+/// #[derive(Phenotype)]
+/// enum Counter {
+///     Shared(Mutex<Box<u32>>),
+///     Off,
+/// }
+///
+/// let pp = ConcurrentPeapod::new();
+/// pp.push(Counter::Shared(Mutex::new(Box::new(0))));
+/// if let Some(counter) = pp.get(0) {
+///     if let Counter::Shared(count) = &*counter {
+///         **count.lock().unwrap() += 1;
+///     }
+/// }
+/// ```
+pub struct ConcurrentPeapod<T>
+where
+    T: Phenotype,
+{
+    // Chunk k holds the elements from (2^k - 1) * FIRST to (2^(k + 1) - 1) * FIRST,
+    // and is null until an element is pushed there
+    chunks: [AtomicPtr<Chunk<T>>; CHUNKS],
+    // The number of elements readers can see. The writer publishes an element
+    // by storing a length that covers it, with Release ordering
+    len: AtomicUsize,
+    writing: AtomicBool,
+}
+
+// # Safety
+// The collection owns its elements, and any thread with a shared reference can
+// push elements in and read them out as `PeaRef`s. Those only exist for `Frozen`
+// elements, so they act like `&T`s, see `PeaRef`. So sending it needs `T: Send`,
+// and sharing it needs `T: Send + Sync`, like a `Mutex<Vec<T>>` that also hands
+// out references
+unsafe impl<T> Send for ConcurrentPeapod<T> where T: Phenotype + Send {}
+unsafe impl<T> Sync for ConcurrentPeapod<T> where T: Phenotype + Send + Sync {}
+
+// A block of tags and a block of values that never moves, for collections that
+// are written to and read from at the same time. Nothing tracks which slots are
+// filled, that's up to the collection.
+pub(crate) struct Chunk<T>
+where
+    T: Phenotype,
+{
+    // Bits are only ever set, once, by the writer before it publishes the
+    // element, so readers can load them without any other synchronization
+    tags: Box<[AtomicUsize]>,
+    data: Box<[UnsafeCell<MaybeUninit<T::Value>>]>,
+}
+
+impl<T> Chunk<T>
+where
+    T: Phenotype,
+{
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            tags: (0..(capacity * T::BITS).div_ceil(WORD_BITS))
+                .map(|_| AtomicUsize::new(0))
+                .collect(),
+            data: (0..capacity)
+                .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                .collect(),
+        }
+    }
+
+    // **Note**: offset must be in range
+    pub(crate) fn tag(&self, offset: usize) -> usize {
+        let start = offset * T::BITS;
+        let mut tag = 0;
+        for bit in 0..T::BITS {
+            let i = start + bit;
+            let word = self.tags[i / WORD_BITS].load(Ordering::Relaxed);
+            tag |= ((word >> (i % WORD_BITS)) & 1) << bit;
+        }
+        tag
+    }
+
+    // **Note**: offset must be in range, and the tag there must not be set yet
+    fn set_tag(&self, offset: usize, tag: usize) {
+        let start = offset * T::BITS;
+        for bit in (0..T::BITS).filter(|bit| tag >> bit & 1 == 1) {
+            let i = start + bit;
+            self.tags[i / WORD_BITS].fetch_or(1 << (i % WORD_BITS), Ordering::Relaxed);
+        }
+    }
+
+    // Takes the element already cleaved, so a panic in `cleave` happens before
+    // the caller has claimed a slot for it.
+    // # Safety
+    // offset must be in range, never written before, and not read by anyone
+    // until the write is published. tag must match value
+    pub(crate) unsafe fn write(&self, offset: usize, tag: usize, value: T::Value) {
+        self.set_tag(offset, tag);
+        (*self.data[offset].get()).write(value);
+    }
+
+    // # Safety
+    // The element at offset must have been written, and the write published
+    pub(crate) unsafe fn get(&self, offset: usize) -> PeaRef<'_, T> {
+        let value = (*self.data[offset].get()).assume_init_ref();
+        PeaRef::new(self.tag(offset), value)
+    }
+
+    // # Safety
+    // The element at offset must have been written, and must not be read again
+    pub(crate) unsafe fn take(&self, offset: usize) -> T {
+        let value = (*self.data[offset].get()).assume_init_read();
+        T::reknit(self.tag(offset), value)
+    }
+}
+
+// Finds the chunk an element is in and its offset in that chunk
+fn locate(index: usize) -> (usize, usize) {
+    // Shifting the index makes the chunk boundaries land on powers of two
+    let shifted = index + (1 << FIRST_SHIFT);
+    let chunk = (usize::BITS - 1 - shifted.leading_zeros() - FIRST_SHIFT) as usize;
+    (chunk, shifted - (1 << (chunk as u32 + FIRST_SHIFT)))
+}
+
+// Releases the write lock, even if pushing panics
+struct WriteGuard<'a>(&'a AtomicBool);
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+impl<T> ConcurrentPeapod<T>
+where
+    T: Phenotype,
+{
+    /// Create a new, empty `ConcurrentPeapod`. This does not allocate.
+    pub const fn new() -> Self {
+        Self {
+            chunks: [const { AtomicPtr::new(ptr::null_mut()) }; CHUNKS],
+            len: AtomicUsize::new(0),
+            writing: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the number of elements that have been pushed. Elements can be
+    /// pushed concurrently, so this can be out of date as soon as it returns,
+    /// but it never goes down.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    /// Returns whether no elements have been pushed yet.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Pushes an element to the end of the collection, and returns its index.
+    ///
+    /// If another thread is pushing at the same time, this waits for it to
+    /// finish.
+    pub fn push(&self, elem: T) -> usize {
+        // Cleave first, so a panic in it doesn't happen while holding the lock
+        let (tag, value) = elem.cleave();
+        let _guard = self.lock();
+
+        // Only the writer changes the length, and we are the writer
+        let index = self.len.load(Ordering::Relaxed);
+        let (chunk, offset) = locate(index);
+        let mut current = self.chunks[chunk].load(Ordering::Acquire);
+        if current.is_null() {
+            current = Box::into_raw(Box::new(Chunk::new(1 << (chunk as u32 + FIRST_SHIFT))));
+            self.chunks[chunk].store(current, Ordering::Release);
+        }
+
+        // # Safety
+        // Chunks are only freed on drop, and this slot is past the length, so no
+        // reader looks at it until we publish it below
+        unsafe { (*current).write(offset, tag, value) };
+
+        self.len.store(index + 1, Ordering::Release);
+        index
+    }
+
+    /// Returns a reference to the element at `index`, or `None` if it hasn't
+    /// been pushed yet. See [`PeaRef`] for how the reference works.
+    pub fn get(&self, index: usize) -> Option<PeaRef<'_, T>>
+    where
+        T: Frozen,
+    {
+        if index >= self.len() {
+            return None;
+        }
+        let (chunk, offset) = locate(index);
+        // # Safety
+        // The element has been published, so its chunk, tag and value were all
+        // written before the length we loaded, and never change again
+        unsafe { Some((*self.chunks[chunk].load(Ordering::Acquire)).get(offset)) }
+    }
+
+    /// Returns the tag of the element at `index`, or `None` if it hasn't been
+    /// pushed yet.
+    pub fn tag(&self, index: usize) -> Option<usize> {
+        if index >= self.len() {
+            return None;
+        }
+        let (chunk, offset) = locate(index);
+        // # Safety
+        // See get
+        unsafe {
+            Some(T::variant(
+                (*self.chunks[chunk].load(Ordering::Acquire)).tag(offset),
+            ))
+        }
+    }
+
+    /// Returns an iterator over the elements that had been pushed when this was
+    /// called. Elements pushed while iterating aren't included.
+    pub fn iter(&self) -> impl Iterator<Item = PeaRef<'_, T>> + '_
+    where
+        T: Frozen,
+    {
+        (0..self.len()).map(|i| self.get(i).expect("elements are never removed"))
+    }
+
+    fn lock(&self) -> WriteGuard<'_> {
+        while self
+            .writing
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            hint::spin_loop();
+        }
+        WriteGuard(&self.writing)
+    }
+}
+
+impl<T> Default for ConcurrentPeapod<T>
+where
+    T: Phenotype,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Debug for ConcurrentPeapod<T>
+where
+    T: Phenotype + Debug + Frozen,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> Extend<T> for ConcurrentPeapod<T>
+where
+    T: Phenotype,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for ConcurrentPeapod<T>
+where
+    T: Phenotype,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut pp = Self::new();
+        pp.extend(iter);
+        pp
+    }
+}
+
+impl<T> Drop for ConcurrentPeapod<T>
+where
+    T: Phenotype,
+{
+    fn drop(&mut self) {
+        let len = *self.len.get_mut();
+        for index in 0..len {
+            let (chunk, offset) = locate(index);
+            // # Safety
+            // Every element below the length was written, and we have unique
+            // access, so each one is read out and dropped exactly once
+            drop(unsafe { (*self.chunks[chunk].load(Ordering::Relaxed)).take(offset) });
+        }
+        for chunk in &mut self.chunks {
+            let chunk = *chunk.get_mut();
+            if !chunk.is_null() {
+                // # Safety
+                // The chunk came from Box::into_raw, and the values in it have
+                // been dropped already, MaybeUninit won't drop them again
+                drop(unsafe { Box::from_raw(chunk) });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use alloc::{sync::Arc, vec::Vec};
+    use phenotype_macro::Phenotype;
+    use std::thread;
+
+    #[derive(Phenotype, PartialEq, Debug)]
+    enum Metric {
+        Count(u64),
+        Gauge(f32),
+        Label(Arc<str>),
+        Missing,
+    }
+
+    #[test]
+    fn locate_chunks() {
+        assert_eq!(locate(0), (0, 0));
+        assert_eq!(locate(31), (0, 31));
+        assert_eq!(locate(32), (1, 0));
+        assert_eq!(locate(95), (1, 63));
+        assert_eq!(locate(96), (2, 0));
+        assert_eq!(
+            locate(usize::MAX - 32),
+            (CHUNKS - 1, (1 << (usize::BITS - 1)) - 1)
+        );
+    }
+
+    #[test]
+    fn push_and_get() {
+        let pp = ConcurrentPeapod::new();
+        assert!(pp.is_empty());
+        for i in 0..1000 {
+            let metric = match i % 4 {
+                0 => Metric::Count(i),
+                1 => Metric::Gauge(i as f32),
+                2 => Metric::Label(Arc::from("label")),
+                _ => Metric::Missing,
+            };
+            assert_eq!(pp.push(metric), i as usize);
+        }
+        assert_eq!(pp.len(), 1000);
+        assert_eq!(*pp.get(400).unwrap(), Metric::Count(400));
+        assert_eq!(*pp.get(401).unwrap(), Metric::Gauge(401.0));
+        assert_eq!(*pp.get(402).unwrap(), Metric::Label(Arc::from("label")));
+        assert_eq!(pp.tag(999), Some(3));
+        assert!(pp.get(1000).is_none());
+        assert_eq!(pp.iter().filter(|m| **m == Metric::Missing).count(), 250);
+    }
+
+    #[test]
+    fn drops_everything() {
+        let label: Arc<str> = Arc::from("shared");
+        let pp: ConcurrentPeapod<_> = (0..500).map(|_| Metric::Label(label.clone())).collect();
+        assert_eq!(Arc::strong_count(&label), 501);
+        drop(pp);
+        assert_eq!(Arc::strong_count(&label), 1);
+    }
+
+    #[test]
+    fn readers_alongside_writers() {
+        let pp = Arc::new(ConcurrentPeapod::new());
+        thread::scope(|s| {
+            for writer in 0..2 {
+                let pp = &pp;
+                s.spawn(move || {
+                    for i in 0..5_000 {
+                        pp.push(Metric::Count(writer * 10_000 + i));
+                    }
+                });
+            }
+            for _ in 0..4 {
+                let pp = Arc::clone(&pp);
+                s.spawn(move || {
+                    let mut seen = 0;
+                    while seen < 10_000 {
+                        // Everything that's been published must be readable
+                        let len = pp.len();
+                        assert!(len >= seen);
+                        assert!(
+                            (seen..len).all(|i| matches!(*pp.get(i).unwrap(), Metric::Count(_)))
+                        );
+                        seen = len;
+                    }
+                });
+            }
+        });
+
+        // Each writer's elements stay in the order they were pushed
+        let counts: Vec<u64> = pp
+            .iter()
+            .map(|m| match *m {
+                Metric::Count(i) => i,
+                _ => unreachable!(),
+            })
+            .collect();
+        for writer in 0..2 {
+            let mine: Vec<_> = counts.iter().filter(|&&i| i / 10_000 == writer).collect();
+            assert_eq!(mine.len(), 5_000);
+            assert!(mine.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use allocator_api2::collections::{TryReserveError, TryReserveErrorKind};
+use core::fmt::{self, Display};
+
+/// The error returned by the fallible allocation methods on
+/// [`Peapod`](crate::Peapod), such as [`try_push`](crate::Peapod::try_push).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeapodError {
+    /// The requested capacity is more than the tags or the data can hold.
+    /// The data can take up at most `isize::MAX` bytes, and the tags at most
+    /// `usize::MAX >> 3` bits.
+    CapacityOverflow,
+    /// The allocator failed to provide the requested memory.
+    AllocError,
+}
+
+impl Display for PeapodError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeapodError::CapacityOverflow => f.write_str("capacity overflow"),
+            PeapodError::AllocError => f.write_str("memory allocation failed"),
+        }
+    }
+}
+
+impl core::error::Error for PeapodError {}
+
+impl From<TryReserveError> for PeapodError {
+    fn from(e: TryReserveError) -> Self {
+        match e.kind() {
+            TryReserveErrorKind::CapacityOverflow => PeapodError::CapacityOverflow,
+            TryReserveErrorKind::AllocError { .. } => PeapodError::AllocError,
+        }
+    }
+}
@@ -3,11 +3,44 @@
 #![no_std]
 
 // Exports :)
-pub use crate::peapod_vec::Peapod;
-pub use phenotype_internal::{Phenotype, PhenotypeDebug};
-pub use phenotype_macro::{Phenotype, PhenotypeDebug};
+pub use crate::btree::{PeaBTreeMap, Range, RangeWithTag};
+pub use crate::concurrent::ConcurrentPeapod;
+pub use crate::error::PeapodError;
+#[cfg(feature = "rayon")]
+pub use crate::par::{IntoParIter, ParIter};
+pub use crate::pea_ref::PeaRef;
+pub use crate::peapod_vec::{DebugVariants, MemoryUsage, Peapod};
+pub use crate::report::{EnumLayout, LayoutReport};
+pub use crate::rle::RlePeapod;
+pub use crate::segmented::SegmentedPeapod;
+pub use crate::slice::{GroupRuns, PeaSlice, PeaSliceMut};
+pub use crate::sparse::SparsePeapod;
+pub use crate::tag_peapod::TagPeapod;
+pub use crate::tag_vec::{TagOrder, TagWord};
+pub use allocator_api2::alloc::{Allocator, Global};
+pub use bitvec::order::{Lsb0, Msb0};
+pub use phenotype_internal::{Frozen, Phenotype, PhenotypeDebug, VariantInfo};
+pub use phenotype_macro::{Frozen, Phenotype, PhenotypeDebug};
 
+mod bitset;
+mod btree;
+mod concurrent;
+mod error;
+#[cfg(feature = "rayon")]
+mod par;
+mod pea_ref;
 mod peapod_vec;
+mod report;
+mod rle;
+mod segmented;
+mod slice;
+mod sort;
+mod sparse;
+mod tag_peapod;
+mod tag_vec;
+#[cfg(test)]
+mod test_util;
 
 // in the works
+#[allow(dead_code)]
 mod array;
@@ -0,0 +1,509 @@
+extern crate alloc;
+use crate::{
+    tag_vec::{TagOrder, TagWord},
+    PeaRef, PeaSlice, Peapod,
+};
+use alloc::collections::LinkedList;
+use allocator_api2::alloc::{Allocator, Global};
+use bitvec::prelude::*;
+use core::{mem, ptr, slice};
+use phenotype_internal::{Frozen, Phenotype};
+use rayon::iter::{
+    plumbing::{bridge, Consumer, Producer, ProducerCallback, UnindexedConsumer},
+    FromParallelIterator, IndexedParallelIterator, IntoParallelIterator, ParallelExtend,
+    ParallelIterator,
+};
+
+/// A parallel iterator over references to the elements of a [`Peapod`] or
+/// [`PeaSlice`], created with `par_iter` or `into_par_iter`.
+///
+/// The elements are split between workers on element boundaries, so each
+/// worker gets a contiguous run of tags.
+pub struct ParIter<'a, T, W = usize, O = Lsb0>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    slice: PeaSlice<'a, T, W, O>,
+}
+
+impl<'a, T, W, O> IntoParallelIterator for PeaSlice<'a, T, W, O>
+where
+    T: Phenotype + Send + Sync + Frozen,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Iter = ParIter<'a, T, W, O>;
+    type Item = PeaRef<'a, T>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParIter { slice: self }
+    }
+}
+
+impl<'a, T, A, W, O> IntoParallelIterator for &'a Peapod<T, A, W, O>
+where
+    T: Phenotype + Send + Sync + Frozen,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Iter = ParIter<'a, T, W, O>;
+    type Item = PeaRef<'a, T>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParIter {
+            slice: self.as_slice(),
+        }
+    }
+}
+
+impl<'a, T, W, O> ParallelIterator for ParIter<'a, T, W, O>
+where
+    T: Phenotype + Send + Sync + Frozen,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Item = PeaRef<'a, T>;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        bridge(self, consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.slice.len())
+    }
+}
+
+impl<T, W, O> IndexedParallelIterator for ParIter<'_, T, W, O>
+where
+    T: Phenotype + Send + Sync + Frozen,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn drive<C>(self, consumer: C) -> C::Result
+    where
+        C: Consumer<Self::Item>,
+    {
+        bridge(self, consumer)
+    }
+
+    fn len(&self) -> usize {
+        self.slice.len()
+    }
+
+    fn with_producer<CB>(self, callback: CB) -> CB::Output
+    where
+        CB: ProducerCallback<Self::Item>,
+    {
+        callback.callback(SliceProducer { slice: self.slice })
+    }
+}
+
+struct SliceProducer<'a, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    slice: PeaSlice<'a, T, W, O>,
+}
+
+impl<'a, T, W, O> Producer for SliceProducer<'a, T, W, O>
+where
+    T: Phenotype + Send + Sync + Frozen,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Item = PeaRef<'a, T>;
+    type IntoIter = crate::slice::Iter<'a, T, W, O>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.slice.iter()
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let (left, right) = self.slice.split_at(index);
+        (Self { slice: left }, Self { slice: right })
+    }
+}
+
+/// A parallel iterator that moves the elements out of a [`Peapod`], created
+/// with `into_par_iter`.
+pub struct IntoParIter<T, A = Global, W = usize, O = Lsb0>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    pod: Peapod<T, A, W, O>,
+}
+
+impl<T, A, W, O> IntoParallelIterator for Peapod<T, A, W, O>
+where
+    T: Phenotype + Send,
+    A: Allocator + Send,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Iter = IntoParIter<T, A, W, O>;
+    type Item = T;
+
+    fn into_par_iter(self) -> Self::Iter {
+        IntoParIter { pod: self }
+    }
+}
+
+impl<T, A, W, O> ParallelIterator for IntoParIter<T, A, W, O>
+where
+    T: Phenotype + Send,
+    A: Allocator + Send,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Item = T;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        bridge(self, consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.pod.len())
+    }
+}
+
+impl<T, A, W, O> IndexedParallelIterator for IntoParIter<T, A, W, O>
+where
+    T: Phenotype + Send,
+    A: Allocator + Send,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn drive<C>(self, consumer: C) -> C::Result
+    where
+        C: Consumer<Self::Item>,
+    {
+        bridge(self, consumer)
+    }
+
+    fn len(&self) -> usize {
+        self.pod.len()
+    }
+
+    fn with_producer<CB>(self, callback: CB) -> CB::Output
+    where
+        CB: ProducerCallback<Self::Item>,
+    {
+        let (tags, mut data) = self.pod.cleave();
+        let len = data.len();
+        // # Safety
+        // The producer takes ownership of the values, so the Vec must forget them.
+        // The values are unions of ManuallyDrop fields, so setting the length
+        // never drops anything
+        let data = unsafe {
+            data.set_len(0);
+            slice::from_raw_parts_mut(data.as_mut_ptr(), len)
+        };
+        // Skip any cruft, see https://github.com/fprasx/peapod/issues/2
+        let producer = DrainProducer::<T, W, O> {
+            tags: &tags[..len * T::BITS],
+            data,
+        };
+        callback.callback(producer)
+        // The allocations are freed here, the elements having been moved or
+        // dropped by the producers
+    }
+}
+
+// Moves elements out of a region of a `Peapod`, reknitting them. Whatever isn't
+// consumed gets dropped along with the producer.
+struct DrainProducer<'a, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    tags: &'a BitSlice<W, O>,
+    data: &'a mut [T::Value],
+}
+
+// # Safety
+// The producer owns the elements in its region, like an `IntoIter` over them
+unsafe impl<T, W, O> Send for DrainProducer<'_, T, W, O>
+where
+    T: Phenotype + Send,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+
+impl<'a, T, W, O> Producer for DrainProducer<'a, T, W, O>
+where
+    T: Phenotype + Send,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Item = T;
+    type IntoIter = Drain<'a, T, W, O>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        // Take the values so they don't get dropped twice
+        let data = mem::take(&mut self.data);
+        Drain {
+            tags: self.tags,
+            front: 0,
+            data: data.iter_mut(),
+        }
+    }
+
+    fn split_at(mut self, index: usize) -> (Self, Self) {
+        // Take the values so they don't get dropped twice
+        let (left, right) = mem::take(&mut self.data).split_at_mut(index);
+        let (left_tags, right_tags) = self.tags.split_at(index * T::BITS);
+        (
+            Self {
+                tags: left_tags,
+                data: left,
+            },
+            Self {
+                tags: right_tags,
+                data: right,
+            },
+        )
+    }
+}
+
+impl<T, W, O> Drop for DrainProducer<'_, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn drop(&mut self) {
+        let data = mem::take(&mut self.data);
+        drop(Drain::<T, W, O> {
+            tags: self.tags,
+            front: 0,
+            data: data.iter_mut(),
+        });
+    }
+}
+
+// The sequential half of `DrainProducer`
+struct Drain<'a, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    tags: &'a BitSlice<W, O>,
+    // The index of the next element from the front, to find its tag
+    front: usize,
+    data: slice::IterMut<'a, T::Value>,
+}
+
+impl<T, W, O> Drain<'_, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    // # Safety
+    // value must be the value at index, and must not be read again
+    unsafe fn reknit(&self, index: usize, value: &mut T::Value) -> T {
+        let tag = O::load_tag(&self.tags[index * T::BITS..(index + 1) * T::BITS]);
+        T::reknit(tag, ptr::read(value))
+    }
+}
+
+impl<T, W, O> Iterator for Drain<'_, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let value = self.data.next()?;
+        self.front += 1;
+        // # Safety
+        // The iterator hands out each value once
+        unsafe { Some(self.reknit(self.front - 1, value)) }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.data.size_hint()
+    }
+}
+
+impl<T, W, O> DoubleEndedIterator for Drain<'_, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let value = self.data.next_back()?;
+        let index = self.front + self.data.len();
+        // # Safety
+        // The iterator hands out each value once
+        unsafe { Some(self.reknit(index, value)) }
+    }
+}
+
+impl<T, W, O> ExactSizeIterator for Drain<'_, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+
+impl<T, W, O> Drop for Drain<'_, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn drop(&mut self) {
+        for _ in self {}
+    }
+}
+
+impl<T, A, W, O> ParallelExtend<T> for Peapod<T, A, W, O>
+where
+    T: Phenotype + Send,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn par_extend<I>(&mut self, par_iter: I)
+    where
+        I: IntoParallelIterator<Item = T>,
+    {
+        // Each worker cleaves its elements into its own Peapod, and then
+        // they're moved over in bulk, in order
+        let pods = par_iter
+            .into_par_iter()
+            .fold(Peapod::<T, Global, W, O>::default, |mut pod, t| {
+                pod.push(t);
+                pod
+            })
+            .map(|pod| LinkedList::from([pod]))
+            .reduce(LinkedList::new, |mut left, mut right| {
+                left.append(&mut right);
+                left
+            });
+        self.reserve(pods.iter().map(Peapod::len).sum());
+        for mut pod in pods {
+            self.append(&mut pod);
+        }
+    }
+}
+
+impl<T, W, O> FromParallelIterator<T> for Peapod<T, Global, W, O>
+where
+    T: Phenotype + Send,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn from_par_iter<I>(par_iter: I) -> Self
+    where
+        I: IntoParallelIterator<Item = T>,
+    {
+        let mut pod = Peapod::default();
+        pod.par_extend(par_iter);
+        pod
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::peapod;
+    use alloc::vec::Vec;
+    use phenotype_macro::Phenotype;
+    use rayon::prelude::*;
+    use std::sync::Arc;
+
+    #[derive(Phenotype, PartialEq, Debug)]
+    enum Job {
+        Run(u64),
+        Wait,
+        Shared(Arc<u64>),
+    }
+
+    fn jobs(n: u64) -> impl ParallelIterator<Item = Job> {
+        (0..n).into_par_iter().map(|i| match i % 3 {
+            0 => Job::Run(i),
+            1 => Job::Wait,
+            _ => Job::Shared(Arc::new(i)),
+        })
+    }
+
+    #[test]
+    fn collect_keeps_order() {
+        let pp: Peapod<Job> = jobs(10_000).collect();
+        assert_eq!(pp.len(), 10_000);
+        assert!((0..pp.len()).all(|i| pp.tag(i) == Some(i % 3)));
+
+        let mut pp = peapod![Job::Wait];
+        pp.par_extend(jobs(4));
+        assert_eq!(
+            Vec::from(pp),
+            [
+                Job::Wait,
+                Job::Run(0),
+                Job::Wait,
+                Job::Shared(Arc::new(2)),
+                Job::Run(3)
+            ]
+        );
+    }
+
+    #[test]
+    fn par_iter_matches_iter() {
+        let pp: Peapod<Job> = jobs(10_000).collect();
+        let runs: u64 = pp
+            .par_iter()
+            .filter_map(|job| match *job {
+                Job::Run(i) => Some(i),
+                _ => None,
+            })
+            .sum();
+        assert_eq!(runs, (0..10_000).step_by(3).sum());
+        assert_eq!(pp.slice(100..200).into_par_iter().count(), 100);
+    }
+
+    #[test]
+    fn into_par_iter_drops_everything() {
+        let shared = Arc::new(0);
+        let pp: Peapod<Job> = (0..10_000)
+            .map(|i| match i % 2 {
+                0 => Job::Shared(shared.clone()),
+                _ => Job::Run(i),
+            })
+            .collect();
+        assert_eq!(Arc::strong_count(&shared), 5_001);
+
+        // Only some of the elements get consumed, the rest are dropped
+        assert!(pp.into_par_iter().any(|job| matches!(job, Job::Run(9_999))));
+        assert_eq!(Arc::strong_count(&shared), 1);
+
+        let pp: Peapod<Job> = (0..1000).map(|_| Job::Shared(shared.clone())).collect();
+        let back: Vec<_> = pp.into_par_iter().rev().collect();
+        assert_eq!(back.len(), 1000);
+        drop(back);
+        assert_eq!(Arc::strong_count(&shared), 1);
+    }
+}
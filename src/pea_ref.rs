@@ -0,0 +1,147 @@
+use core::{
+    cmp::Ordering,
+    fmt::{self, Debug, Display},
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ops::Deref,
+    ptr,
+};
+use phenotype_internal::{Frozen, Phenotype};
+
+/// A borrowed view of an element that is stored `cleave`d, for example inside
+/// a [`Peapod`](crate::Peapod).
+///
+/// Cleaved elements aren't stored as `T`s, so there is no `T` to hand out a `&T`
+/// to. Instead, a `PeaRef` `reknit`s a bitwise copy of the stored data and derefs
+/// to that. The copy is never dropped, so the collection keeps ownership of any
+/// resources the element holds.
+///
+/// Interior mutability would act on the copy, and could move owned data out
+/// of it that the collection still owns, e.g. with `Cell::take` on a
+/// `Cell<Option<Box<_>>>`. So a `PeaRef` only derefs if `T` is [`Frozen`],
+/// which rules out a `Cell` anywhere in an element that isn't behind a pointer:
+///
+/// ```compile_fail
+/// use core::cell::Cell;
+/// use peapod::{peapod, Phenotype};
+///
+/// #[derive(Phenotype)]
+/// enum Slot {
+///     Full(Cell<Option<Box<u64>>>),
+///     Empty,
+/// }
+///
+/// let pp = peapod![Slot::Full(Cell::new(Some(Box::new(1)))), Slot::Empty];
+/// if let Slot::Full(cell) = &*pp.get(0).unwrap() {
+///     cell.take();
+/// }
+/// ```
+pub struct PeaRef<'a, T> {
+    elem: ManuallyDrop<T>,
+    // Like a `&'a T`, without the auto traits of `T` the `ManuallyDrop` brings
+    _borrow: PhantomData<(&'a (), *const T)>,
+}
+
+// # Safety
+// The copy is only ever accessed through `&T` and is never dropped. `T: Frozen`
+// means it holds no `UnsafeCell` of its own, so what threads can reach through
+// it, and race on, is exactly what they could through a `&T` to the stored
+// element: whatever that points to. So it can go anywhere a `&T` could
+unsafe impl<T> Send for PeaRef<'_, T> where T: Sync + Frozen {}
+unsafe impl<T> Sync for PeaRef<'_, T> where T: Sync + Frozen {}
+
+impl<'a, T> PeaRef<'a, T>
+where
+    T: Phenotype,
+{
+    /// # Safety
+    /// `tag` must match the variant `value` holds, as with [`Phenotype::reknit`].
+    pub(crate) unsafe fn new(tag: usize, value: &'a T::Value) -> Self {
+        Self {
+            // # Safety
+            // We are reading from a reference, and the copy is wrapped in
+            // ManuallyDrop so it is never dropped twice
+            elem: ManuallyDrop::new(T::reknit(tag, ptr::read(value))),
+            _borrow: PhantomData,
+        }
+    }
+
+    /// # Safety
+    /// `tag` must match the variant `value` holds, as with [`Phenotype::reknit`].
+    /// Like with `new`, the reknit element is never dropped, so `value` should be
+    /// a copy of data owned elsewhere, or hold nothing that needs dropping.
+    pub(crate) unsafe fn from_value(tag: usize, value: T::Value) -> Self {
+        Self {
+            elem: ManuallyDrop::new(T::reknit(tag, value)),
+            _borrow: PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for PeaRef<'_, T>
+where
+    T: Frozen,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.elem
+    }
+}
+
+impl<T> Debug for PeaRef<'_, T>
+where
+    T: Debug + Frozen,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&*self.elem, f)
+    }
+}
+
+impl<T> Display for PeaRef<'_, T>
+where
+    T: Display + Frozen,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&*self.elem, f)
+    }
+}
+
+impl<T> PartialEq for PeaRef<'_, T>
+where
+    T: PartialEq + Frozen,
+{
+    fn eq(&self, other: &Self) -> bool {
+        *self.elem == *other.elem
+    }
+}
+
+impl<T> Eq for PeaRef<'_, T> where T: Eq + Frozen {}
+
+impl<T> PartialOrd for PeaRef<'_, T>
+where
+    T: PartialOrd + Frozen,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (*self.elem).partial_cmp(&*other.elem)
+    }
+}
+
+impl<T> Ord for PeaRef<'_, T>
+where
+    T: Ord + Frozen,
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        (*self.elem).cmp(&*other.elem)
+    }
+}
+
+impl<T> Hash for PeaRef<'_, T>
+where
+    T: Hash + Frozen,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (*self.elem).hash(state)
+    }
+}
@@ -1,13 +1,25 @@
 extern crate alloc;
-use alloc::{format, vec::Vec};
-use bitvec::{field::BitField, prelude::*};
+use crate::{
+    slice::{self, PeaSlice, PeaSliceMut},
+    tag_vec::{TagOrder, TagVec, TagWord},
+    PeaRef, PeapodError,
+};
+use alloc::{vec, vec::Vec};
+use allocator_api2::{
+    alloc::{Allocator, Global},
+    vec::Vec as AllocVec,
+};
+use bitvec::prelude::*;
 use core::{
     cmp,
     fmt::{self, Debug, Display},
-    mem::ManuallyDrop,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    mem::{self, ManuallyDrop},
+    ops::RangeBounds,
     ptr,
 };
-use phenotype_internal::Phenotype;
+use phenotype_internal::{Frozen, Phenotype, PhenotypeDebug};
 
 // credit: https://veykril.github.io/tlborm/decl-macros/building-blocks/counting.html#bit-twiddling
 #[doc(hidden)]
@@ -53,10 +65,69 @@ macro_rules! peapod {
 }
 
 /// A `vec`-like data structure for compactly storing `enum`s that implement [`Phenotype`].
-#[derive(Eq)]
-pub struct Peapod<T: Phenotype> {
-    tags: BitVec,
-    data: Vec<T::Value>,
+///
+/// Both the tags and the data are allocated through `A`, which defaults to the
+/// global allocator. See [`Peapod::new_in`].
+///
+/// The tags are packed into [`TagWord`]s in a [`TagOrder`], `usize`s and [`Lsb0`]
+/// by default. Picking a fixed-size word gives the same layout on every platform,
+/// see [`Peapod::tag_words`]. Collections with a non-default layout are created
+/// with [`Default`], [`FromIterator`] or [`From<Vec<T>>`](From).
+///
+/// ## Thread safety
+/// A `Peapod<T>` is [`Send`] when `T` is, and [`Sync`] when `T` is, just like a
+/// `Vec<T>`. So a `Peapod` of elements that can't leave their thread can't either:
+/// ```compile_fail
+/// # use peapod::{Peapod, Phenotype};
+/// # use std::rc::Rc;
+/// #[derive(Phenotype)]
+/// enum Local {
+///     Shared(Rc<u8>),
+///     Empty,
+/// }
+///
+/// fn send<S: Send>(_: S) {}
+/// send(Peapod::<Local>::new());
+/// ```
+/// ```compile_fail
+/// # use peapod::{Peapod, Phenotype};
+/// # use std::cell::Cell;
+/// #[derive(Phenotype)]
+/// enum Counter {
+///     Count(Cell<u8>),
+///     Empty,
+/// }
+///
+/// fn sync<S: Sync>(_: &S) {}
+/// sync(&Peapod::<Counter>::new());
+/// ```
+pub struct Peapod<T: Phenotype, A: Allocator = Global, W: TagWord = usize, O: TagOrder = Lsb0> {
+    tags: TagVec<A, W, O>,
+    data: AllocVec<T::Value, A>,
+    // The tags and data don't hold any `T`s, so they don't tell the compiler
+    // when a `Peapod` is safe to send or share. This opts out of the auto
+    // traits, and the impls below opt back in based on `T`
+    _marker: PhantomData<*const T>,
+}
+
+// # Safety
+// A `Peapod` owns its elements like a `Vec<T, A>` does
+unsafe impl<T, A, W, O> Send for Peapod<T, A, W, O>
+where
+    T: Phenotype + Send,
+    A: Allocator + Send,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+
+unsafe impl<T, A, W, O> Sync for Peapod<T, A, W, O>
+where
+    T: Phenotype + Sync,
+    A: Allocator + Sync,
+    W: TagWord,
+    O: TagOrder,
+{
 }
 
 impl<T> Peapod<T>
@@ -65,32 +136,278 @@ where
 {
     /// Create a new `Peapod` with 0 capacity and 0 length. This does not allocate.
     pub fn new() -> Self {
-        Peapod {
-            tags: BitVec::new(),
-            data: Vec::new(),
+        Self::new_in(Global)
+    }
+
+    /// Creates a new peapod with enough space to add `capacity` elements
+    /// without reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+
+    /// Tries to create a new peapod with enough space to add `capacity` elements
+    /// without reallocating.
+    ///
+    /// ## Errors
+    /// Returns an error if the capacity would overflow or the allocator fails.
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, PeapodError> {
+        Self::try_with_capacity_in(capacity, Global)
+    }
+
+    /// Creates a collection from an iterator that knows its exact length,
+    /// allocating exactly once. See [`Peapod::extend_from_iter_exact`].
+    pub fn from_trusted_len<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let mut pp = Peapod::new();
+        pp.extend_from_iter_exact(iter);
+        pp
+    }
+
+    /// Joins a sequence of collections into one, in order. Like
+    /// [`Peapod::append`], elements are moved in bulk without being reknit.
+    pub fn concat<I>(pods: I) -> Self
+    where
+        I: IntoIterator<Item = Peapod<T>>,
+    {
+        let mut pods = pods.into_iter();
+        // Reuse the first collection's allocation
+        let mut joined = pods.next().unwrap_or_default();
+        for mut pod in pods {
+            joined.append(&mut pod);
         }
+        joined
+    }
+}
+
+impl<T, A> Peapod<T, A>
+where
+    T: Phenotype,
+    A: Allocator + Clone,
+{
+    /// Creates a new `Peapod` with 0 capacity and 0 length, that will allocate
+    /// through `alloc`. This does not allocate.
+    pub fn new_in(alloc: A) -> Self {
+        Self::from_parts(TagVec::new_in(alloc.clone()), AllocVec::new_in(alloc))
+    }
+
+    /// Creates a new peapod with enough space to add `capacity` elements
+    /// without reallocating, that will allocate through `alloc`.
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        Self::from_parts(
+            TagVec::with_capacity_in(capacity * T::BITS, alloc.clone()),
+            AllocVec::with_capacity_in(capacity, alloc),
+        )
+    }
+
+    /// Tries to create a new peapod with enough space to add `capacity` elements
+    /// without reallocating, that will allocate through `alloc`.
+    ///
+    /// ## Errors
+    /// Returns an error if the capacity would overflow or the allocator fails.
+    pub fn try_with_capacity_in(capacity: usize, alloc: A) -> Result<Self, PeapodError> {
+        let mut pp = Self::new_in(alloc);
+        pp.try_reserve(capacity)?;
+        Ok(pp)
+    }
+}
+
+impl<T, A, W, O> Peapod<T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator + Clone,
+    W: TagWord,
+    O: TagOrder,
+{
+    // Like `new_in`, but for any tag layout. `new_in` sticks to the default one
+    // so the layout doesn't need spelling out wherever it's called
+    pub(crate) fn empty_in(alloc: A) -> Self {
+        Self::from_parts(TagVec::new_in(alloc.clone()), AllocVec::new_in(alloc))
+    }
+
+    /// Splits the collection in two at `at`. `self` keeps the elements before
+    /// `at`, and the elements from `at` on are returned in a new collection.
+    /// The elements are moved in bulk, without being reknit.
+    ///
+    /// ## Panics
+    /// Panics if `at > len`.
+    pub fn split_off(&mut self, at: usize) -> Self {
+        let len = self.data.len();
+        assert!(
+            at <= len,
+            "`at` split index (is {at}) should be <= len (is {len})"
+        );
+        let data = self.data.split_off(at);
+        let mut tags = self.tags.split_off(at * T::BITS);
+        // Leave any cruft behind, see https://github.com/fprasx/peapod/issues/2
+        tags.truncate((len - at) * T::BITS);
+        Self::from_parts(tags, data)
+    }
+
+    /// Removes consecutive repeated elements, keeping the first of each run.
+    pub fn dedup(&mut self)
+    where
+        T: PartialEq + Frozen,
+    {
+        self.dedup_by(|a, b| a == b)
+    }
+
+    /// Removes consecutive elements that map to the same key, keeping the first
+    /// of each run.
+    pub fn dedup_by_key<K, F>(&mut self, mut key: F)
+    where
+        T: Frozen,
+        F: FnMut(&T) -> K,
+        K: PartialEq,
+    {
+        self.dedup_by(|a, b| key(a) == key(b))
+    }
+
+    /// Removes consecutive elements that `same_bucket` considers equal, keeping
+    /// the first of each run. `same_bucket` is passed the element being checked
+    /// and the last element that was kept, in that order.
+    ///
+    /// If `same_bucket` panics, the collection is left as it was.
+    pub fn dedup_by<F>(&mut self, mut same_bucket: F)
+    where
+        T: Frozen,
+        F: FnMut(&T, &T) -> bool,
+    {
+        if self.len() < 2 {
+            return;
+        }
+        let mut keep = Vec::with_capacity(self.len());
+        keep.push(0);
+        for i in 1..self.len() {
+            let last = keep[keep.len() - 1];
+            if !same_bucket(&self.view(i), &self.view(last)) {
+                keep.push(i);
+            }
+        }
+        if keep.len() < self.len() {
+            // The duplicates get dropped here
+            self.keep_only(&keep);
+        }
+    }
+
+    // Keeps the elements at the indices in `keep` and returns the rest, both in order.
+    // **Note**: keep must be strictly increasing and in bounds
+    pub(crate) fn keep_only(&mut self, keep: &[usize]) -> Self {
+        let len = self.len();
+        let alloc = self.allocator();
+        let mut kept = (
+            TagVec::with_capacity_in(keep.len() * T::BITS, alloc.clone()),
+            AllocVec::with_capacity_in(keep.len(), alloc.clone()),
+        );
+        let mut removed = (
+            TagVec::with_capacity_in((len - keep.len()) * T::BITS, alloc.clone()),
+            AllocVec::with_capacity_in(len - keep.len(), alloc.clone()),
+        );
+        let mut keep = keep.iter().peekable();
+        for i in 0..len {
+            let (tags, data) = if keep.next_if_eq(&&i).is_some() {
+                &mut kept
+            } else {
+                &mut removed
+            };
+            tags.extend_from_bitslice(&self.tags[i * T::BITS..(i + 1) * T::BITS]);
+            // # Safety
+            // Every value is read exactly once. The values are unions of
+            // ManuallyDrop fields, so if this panics, the copies don't get dropped
+            data.push(unsafe { ptr::read(&self.data[i]) });
+        }
+        // # Safety
+        // All the values have been moved out, and they don't need dropping
+        unsafe { self.data.set_len(0) };
+
+        (self.tags, self.data) = kept;
+        Self::from_parts(removed.0, removed.1)
+    }
+
+    // Reorders the elements so the element at `order[i]` ends up at `i`.
+    // **Note**: order must be a permutation of 0..len
+    pub(crate) fn permute(&mut self, order: &[usize]) {
+        debug_assert_eq!(order.len(), self.len());
+
+        let alloc = self.allocator().clone();
+        let mut tags = TagVec::with_capacity_in(order.len() * T::BITS, alloc.clone());
+        tags.resize(order.len() * T::BITS, false);
+        for (new, &old) in order.iter().enumerate() {
+            O::store_tag(
+                &mut tags[new * T::BITS..(new + 1) * T::BITS],
+                self.get_tag(old),
+            );
+        }
+
+        // # Safety
+        // Every value is read exactly once as order is a permutation. The values
+        // are unions of ManuallyDrop fields, so if this panics, the copies don't
+        // get dropped
+        let mut data = AllocVec::with_capacity_in(order.len(), alloc);
+        data.extend(
+            order
+                .iter()
+                .map(|&old| unsafe { ptr::read(&self.data[old]) }),
+        );
+        // # Safety
+        // All the values have been moved out, and they don't need dropping
+        unsafe { self.data.set_len(0) };
+
+        self.tags = tags;
+        self.data = data;
+    }
+}
+
+impl<T, A, W, O> Peapod<T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    // **Note**: tags must hold exactly one valid tag for each value in data
+    pub(crate) fn from_parts(tags: TagVec<A, W, O>, data: AllocVec<T::Value, A>) -> Self {
+        Self {
+            tags,
+            data,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns a reference to the allocator the collection allocates through.
+    pub fn allocator(&self) -> &A {
+        self.data.allocator()
     }
 
     // **Note**: index must be in range
-    fn get_tag(&self, index: usize) -> usize {
-        self.tags[index * T::BITS..(index + 1) * T::BITS].load()
+    pub(crate) fn get_tag(&self, index: usize) -> usize {
+        self.tags.load(index * T::BITS, T::BITS)
+    }
+
+    // Which variant the element at index is, without any fields packed into
+    // its tag. **Note**: index must be in range
+    pub(crate) fn get_variant(&self, index: usize) -> usize {
+        T::variant(self.get_tag(index))
     }
 
     // **Note**: index must be in range
-    fn set_tag(&mut self, index: usize, tag: usize) {
-        self.tags[index * T::BITS..(index + 1) * T::BITS].store::<usize>(tag);
+    pub(crate) fn set_tag(&mut self, index: usize, tag: usize) {
+        self.tags.store(index * T::BITS, T::BITS, tag);
     }
 
     /// Append a new element to the end of the collection.
-    /// 
+    ///
     /// ## Panics
     /// Panics if the underlying `bitvec` or `Vec` panics.
-    /// The underlying [`Vec`](https://doc.rust-lang.org/stable/std/vec/struct.Vec.html#panics-7) 
+    /// The underlying [`Vec`](https://doc.rust-lang.org/stable/std/vec/struct.Vec.html#panics-7)
     /// will panic if its allocation exceeds
     /// `isize::MAX` bytes. The underlying `bitvec` will panic
     /// if the maximum tag capacity is exceeded.
     /// On 32-bit systems, maximum tag capacity is `0x1fff_ffff/T::BITS` tags.
     /// On 64-bit systems, maximum tag capacity is `0x1fff_ffff_ffff_ffff/T::BITS` tags.
+    /// Use [`try_push`](Self::try_push) to get an error instead.
     pub fn push(&mut self, t: T) {
         let pos = self.data.len();
 
@@ -99,6 +416,8 @@ where
         // Naively pushing seems to be faster than something like
         // self.tags
         //     .extend_from_bitslice(&BitView::view_bits::<Lsb0>(&[tag])[0..T::BITS]);
+        // or self.tags.resize(..). To push many elements quickly, use `extend`,
+        // which writes whole words of tags at a time.
         for _ in 0..T::BITS {
             self.tags.push(false)
         }
@@ -106,7 +425,7 @@ where
         // https://github.com/fprasx/peapod/issues/2
         // We have to push the data second because pushing to
         // self.tags will panic if capacity is exceeded.
-        // If this panic is caught and we already pushed a 
+        // If this panic is caught and we already pushed a
         // value to self.data, but not self.tags, there
         // will be an untagged value on the end of self.data.
         //
@@ -145,6 +464,160 @@ where
         unsafe { Some(Phenotype::reknit(tag, data)) }
     }
 
+    /// Inserts an element at position `index`, shifting all elements after it
+    /// to the right.
+    ///
+    /// ## Panics
+    /// Panics if `index > len`, and in the same cases as [`Peapod::push`].
+    pub fn insert(&mut self, index: usize, t: T) {
+        let len = self.data.len();
+        assert!(
+            index <= len,
+            "insertion index (is {index}) should be <= len (is {len})"
+        );
+
+        let (tag, data) = t.cleave();
+
+        // Get rid of any cruft so the new tag bits end up right after the last tag,
+        // see https://github.com/fprasx/peapod/issues/2
+        self.tags.truncate(len * T::BITS);
+        for _ in 0..T::BITS {
+            self.tags.push(false)
+        }
+
+        // Like in push, data goes second, as self.tags is the one that can panic
+        self.data.insert(index, data);
+
+        // Shift the tags after index over by one slot to make room
+        self.tags[index * T::BITS..].rotate_right(T::BITS);
+        self.set_tag(index, tag);
+    }
+
+    /// Removes and returns the element at position `index`, shifting all elements
+    /// after it to the left.
+    ///
+    /// ## Panics
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        let len = self.data.len();
+        assert!(
+            index < len,
+            "removal index (is {index}) should be < len (is {len})"
+        );
+
+        let tag = self.get_tag(index);
+        let data = self.data.remove(index);
+
+        // Move the removed tag to the end, then chop it off
+        self.tags[index * T::BITS..len * T::BITS].rotate_left(T::BITS);
+        self.tags.truncate((len - 1) * T::BITS);
+
+        // # Safety
+        // The tag matches the data
+        unsafe { Phenotype::reknit(tag, data) }
+    }
+
+    // **Note**: index must be in range
+    pub(crate) fn view(&self, index: usize) -> PeaRef<'_, T> {
+        // # Safety
+        // The tag matches the data
+        unsafe { PeaRef::new(self.get_tag(index), &self.data[index]) }
+    }
+
+    /// Returns a reference to the element at `index`, or `None` if it is out
+    /// of bounds.
+    pub fn get(&self, index: usize) -> Option<PeaRef<'_, T>> {
+        (index < self.len()).then(|| self.view(index))
+    }
+
+    /// Returns the tag of the element at `index`, or `None` if it is out
+    /// of bounds. This says which variant it is: any fields packed into the
+    /// tag with `#[phenotype(bits = N)]` are left out.
+    pub fn tag(&self, index: usize) -> Option<usize> {
+        (index < self.len()).then(|| self.get_variant(index))
+    }
+
+    /// Returns the tags of all the elements, packed into `W`s in the order `O`,
+    /// `T::BITS` bits each. See [`TagOrder`] for how they're laid out.
+    pub fn tag_bits(&self) -> &BitSlice<W, O> {
+        &self.tags[..self.len() * T::BITS]
+    }
+
+    // The tags as `tag_bits` returns them, for rewriting in place. Whatever is
+    // written has to be valid tags for the data alongside them
+    pub(crate) fn tag_bits_mut(&mut self) -> &mut BitSlice<W, O> {
+        let bits = self.len() * T::BITS;
+        &mut self.tags[..bits]
+    }
+
+    /// Returns the words the tags are packed into, see [`Peapod::tag_bits`].
+    ///
+    /// With a fixed-size `W`, like `u8`, the layout is the same on every
+    /// platform, so the words can be written out or handed to other code as is.
+    /// Bits past the last tag in the last word can be anything.
+    ///
+    /// ```rust
+    /// # use peapod::{Global, Msb0, Peapod, Phenotype};
+    /// #[derive(Phenotype)]
+    /// enum Light {
+    ///     Red,
+    ///     Yellow,
+    ///     Green,
+    /// }
+    ///
+    /// let pp: Peapod<Light, Global, u8, Msb0> = [Light::Green, Light::Red, Light::Yellow]
+    ///     .into_iter()
+    ///     .collect();
+    /// // Two bits per tag, from the most significant bit down
+    /// assert_eq!(pp.tag_words()[0] >> 2, 0b10_00_01);
+    /// ```
+    pub fn tag_words(&self) -> &[W] {
+        self.tags.words(self.len() * T::BITS)
+    }
+
+    /// Returns an iterator over references to the elements of the collection.
+    pub fn iter(&self) -> slice::Iter<'_, T, W, O> {
+        self.as_slice().iter()
+    }
+
+    /// Returns a view of the whole collection as a [`PeaSlice`].
+    pub fn as_slice(&self) -> PeaSlice<'_, T, W, O> {
+        // Skip any cruft, see https://github.com/fprasx/peapod/issues/2
+        PeaSlice::new(&self.tags[..self.data.len() * T::BITS], &self.data)
+    }
+
+    /// Returns a mutable view of the whole collection as a [`PeaSliceMut`].
+    pub fn as_mut_slice(&mut self) -> PeaSliceMut<'_, T, W, O> {
+        // Skip any cruft, see https://github.com/fprasx/peapod/issues/2
+        PeaSliceMut::new(&mut self.tags[..self.data.len() * T::BITS], &mut self.data)
+    }
+
+    /// Returns a view of the elements in `range`, without copying them.
+    ///
+    /// ## Panics
+    /// Panics if `range` is out of bounds.
+    pub fn slice<R>(&self, range: R) -> PeaSlice<'_, T, W, O>
+    where
+        R: RangeBounds<usize>,
+    {
+        self.as_slice().slice(range)
+    }
+
+    /// Returns a mutable view of the elements in `range`, without copying them.
+    ///
+    /// ## Panics
+    /// Panics if `range` is out of bounds.
+    pub fn slice_mut<R>(&mut self, range: R) -> PeaSliceMut<'_, T, W, O>
+    where
+        R: RangeBounds<usize>,
+    {
+        let range = slice::to_range(range, self.len());
+        PeaSliceMut::new(
+            &mut self.tags[range.start * T::BITS..range.end * T::BITS],
+            &mut self.data[range],
+        )
+    }
+
     /// Returns the number of elements in the collection.
     pub fn len(&self) -> usize {
         self.data.len()
@@ -161,13 +634,58 @@ where
         self.tags.reserve(elements * T::BITS);
     }
 
-    /// Creates a new peapod with enough space to add `capacity` elements
-    /// without reallocating.
-    pub fn with_capacity(capacity: usize) -> Self {
-        Self {
-            tags: BitVec::with_capacity(capacity * T::BITS),
-            data: Vec::with_capacity(capacity),
+    /// Tries to reserve space for at least `additional` more elements, so that
+    /// the next `additional` pushes won't allocate.
+    ///
+    /// ## Errors
+    /// Returns an error if the capacity would overflow or the allocator fails.
+    /// The elements are left unchanged, although the capacity of the data may
+    /// have grown even if reserving space for the tags then fails.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), PeapodError> {
+        // Get rid of any cruft so we reserve from where the next tag goes
+        self.tags.truncate(self.len() * T::BITS);
+        let bits = additional
+            .checked_mul(T::BITS)
+            .ok_or(PeapodError::CapacityOverflow)?;
+        self.data.try_reserve(additional)?;
+        self.tags.try_reserve(bits)
+    }
+
+    /// Tries to append an element to the end of the collection.
+    ///
+    /// ## Errors
+    /// Returns an error if the capacity would overflow or the allocator fails,
+    /// in which case the collection is unchanged and `t` is dropped.
+    pub fn try_push(&mut self, t: T) -> Result<(), PeapodError> {
+        self.try_reserve(1)?;
+        // This won't allocate, so it won't panic
+        self.push(t);
+        Ok(())
+    }
+
+    /// Tries to append every element of `iter` to the end of the collection.
+    ///
+    /// ## Errors
+    /// Returns an error if the capacity would overflow or the allocator fails.
+    /// Any elements appended so far are dropped, so the collection holds the
+    /// same elements as before, although its capacity may have grown, like with
+    /// [`Peapod::try_reserve`]. The rest of `iter` is not consumed.
+    pub fn try_extend<I>(&mut self, iter: I) -> Result<(), PeapodError>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let len = self.len();
+        let mut iter = iter.into_iter();
+        let result = self
+            .try_reserve(iter.size_hint().0)
+            .and_then(|()| iter.try_for_each(|t| self.try_push(t)));
+        if result.is_err() {
+            // truncate would only forget them, so reknit and drop them one by one
+            while self.len() > len {
+                self.pop();
+            }
         }
+        result
     }
 
     /// Removes all elements from the collection.
@@ -182,29 +700,169 @@ where
     pub fn truncate(&mut self, len: usize) {
         // https://github.com/fprasx/peapod/issues/2
         // len  * T::BITS can overflow so we saturate at the top,
-        // If it overflows this means len > max-capacity of the bitvec, 
+        // If it overflows this means len > max-capacity of the bitvec,
         // so it would be impossible to reach a state with that many elements.
         // Therefore saturating at the top won't remove anything - which is correct
         self.tags.truncate(usize::saturating_mul(len, T::BITS));
         self.data.truncate(len);
     }
 
+    /// Reserves space for exactly `additional` more elements, without the
+    /// extra room [`Peapod::reserve`] leaves to amortize future growth.
+    ///
+    /// ## Panics
+    /// Panics in the same cases as [`Peapod::push`].
+    pub fn reserve_exact(&mut self, additional: usize) {
+        // Get rid of any cruft so we reserve from where the next tag goes
+        self.tags.truncate(self.len() * T::BITS);
+        self.data.reserve_exact(additional);
+        self.tags.reserve_exact(additional * T::BITS);
+    }
+
+    /// Shrinks the capacity of the collection as much as possible, giving
+    /// memory back to the allocator.
+    pub fn shrink_to_fit(&mut self) {
+        // Cruft would keep its words alive, see https://github.com/fprasx/peapod/issues/2
+        self.tags.truncate(self.len() * T::BITS);
+        self.data.shrink_to_fit();
+        self.tags.shrink_to_fit();
+    }
+
+    /// Shrinks the capacity of the collection to hold at least `min_capacity`
+    /// elements. The capacity never drops below the length.
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        let min_capacity = cmp::max(min_capacity, self.len());
+        self.tags.truncate(self.len() * T::BITS);
+        self.data.shrink_to(min_capacity);
+        self.tags.shrink_to(min_capacity.saturating_mul(T::BITS));
+    }
+
+    /// Returns a breakdown of the memory the collection has allocated.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let value = mem::size_of::<T::Value>();
+        let (tag_bytes, tag_capacity) = self.tags.bytes();
+        MemoryUsage {
+            tag_bytes,
+            data_bytes: self.data.len() * value,
+            spare_tag_bytes: tag_capacity - tag_bytes,
+            spare_data_bytes: (self.data.capacity() - self.data.len()) * value,
+        }
+    }
+
     /// Returns the number of elements the collection can hold
     /// without reallocating.
     pub fn capacity(&self) -> usize {
-        let tag_cap = self.tags.capacity() / T::BITS;
         let data_cap = self.data.capacity();
-        cmp::min(tag_cap, data_cap)
+        match T::BITS {
+            0 => data_cap,
+            bits => cmp::min(self.tags.capacity() / bits, data_cap),
+        }
+    }
+
+    /// Divides the collection into two views at `mid`. The first contains the
+    /// elements before `mid`, and the second the elements from `mid` on.
+    ///
+    /// ## Panics
+    /// Panics if `mid > len`.
+    pub fn split_at(&self, mid: usize) -> (PeaSlice<'_, T, W, O>, PeaSlice<'_, T, W, O>) {
+        self.as_slice().split_at(mid)
+    }
+
+    /// Moves all the elements of `other` to the end of `self`, leaving `other`
+    /// empty. The tags and data are moved in bulk, without being reknit.
+    ///
+    /// ## Panics
+    /// Panics in the same cases as [`Peapod::push`].
+    pub fn append<B>(&mut self, other: &mut Peapod<T, B, W, O>)
+    where
+        B: Allocator,
+    {
+        let len = self.data.len();
+        let other_len = other.data.len();
+
+        // Get rid of any cruft so other's tags end up right after ours, and
+        // don't bring other's cruft along, see https://github.com/fprasx/peapod/issues/2
+        self.tags.truncate(len * T::BITS);
+        self.tags
+            .extend_from_bitslice(&other.tags[..other_len * T::BITS]);
+
+        // Like in push, data goes second, as it's what determines the length
+        // # Safety
+        // The values are moved over bitwise, then forgotten by other
+        self.data.reserve(other_len);
+        unsafe {
+            ptr::copy_nonoverlapping(
+                other.data.as_ptr(),
+                self.data.as_mut_ptr().add(len),
+                other_len,
+            );
+            other.data.set_len(0);
+            self.data.set_len(len + other_len);
+        }
+        other.tags.clear();
     }
 
-    /// Adds `other` to the end of `self`, so the new collection
-    /// now contains all the elements of `self` followed by the elements
-    /// of `other`.
-    pub fn append(&mut self, other: Peapod<T>) {
-        self.extend(other.into_iter());
+    /// Appends every element of `iter`, which must know its exact length.
+    /// Space for all the elements is reserved up front, and the tags are written
+    /// a word at a time.
+    ///
+    /// **Note**: the length is only used to size the allocations, so an
+    /// iterator that gets its length wrong is still handled correctly.
+    pub fn extend_from_iter_exact<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let iter = iter.into_iter();
+        self.data.reserve_exact(iter.len());
+        self.tags.reserve_exact(iter.len() * T::BITS);
+        let mut writer = TagWriter::new(self);
+        for elem in iter {
+            writer.push(elem);
+        }
     }
 
-    fn cleave(self) -> (BitVec, Vec<T::Value>) {
+    // Pushes an element that has already been cleaved.
+    // # Safety
+    // The tag must match the data
+    pub(crate) unsafe fn push_cleaved(&mut self, tag: usize, data: T::Value) {
+        let pos = self.data.len();
+        // See push for why things are done in this order
+        for _ in 0..T::BITS {
+            self.tags.push(false)
+        }
+        self.data.push(data);
+        self.set_tag(pos, tag);
+    }
+
+    /// Returns a value that `Debug` formats as the number of elements of each
+    /// variant, keyed by variant name. Use `{:#?}` to print one variant per line.
+    /// `{:#?}` on the `Peapod` itself prints the same counts, but needs `T: Debug`
+    /// and names variants without the enum's name.
+    ///
+    /// ```
+    /// use peapod::{peapod, Phenotype, PhenotypeDebug};
+    ///
+    /// #[derive(Phenotype, PhenotypeDebug)]
+    /// enum Event {
+    ///     Open(u32),
+    ///     Close(u32),
+    /// }
+    ///
+    /// let pp = peapod![Event::Open(1), Event::Open(2), Event::Close(1)];
+    /// assert_eq!(
+    ///     format!("{:?}", pp.debug_variants()),
+    ///     "{Event::Open: 2, Event::Close: 1}"
+    /// );
+    /// ```
+    pub fn debug_variants(&self) -> DebugVariants<'_, T, A, W, O>
+    where
+        T: PhenotypeDebug,
+    {
+        DebugVariants { pod: self }
+    }
+
+    pub(crate) fn cleave(self) -> (TagVec<A, W, O>, AllocVec<T::Value, A>) {
         let levitating = ManuallyDrop::new(self);
         unsafe {
             (
@@ -218,51 +876,64 @@ where
     }
 }
 
-impl<T> Drop for Peapod<T>
+impl<T, A, W, O> Drop for Peapod<T, A, W, O>
 where
     T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
 {
     fn drop(&mut self) {
         while self.pop().is_some() {}
     }
 }
 
-impl<T> From<Peapod<T>> for Vec<T>
+impl<T, A, W, O> From<Peapod<T, A, W, O>> for Vec<T>
 where
     T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
 {
-    fn from(pp: Peapod<T>) -> Self {
+    fn from(pp: Peapod<T, A, W, O>) -> Self {
         pp.into_iter().collect()
     }
 }
 
-impl<T> From<Vec<T>> for Peapod<T>
+impl<T, W, O> From<Vec<T>> for Peapod<T, Global, W, O>
 where
     T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
 {
     fn from(v: Vec<T>) -> Self {
-        let mut pp = Peapod::with_capacity(v.len());
-        pp.extend(v.into_iter());
+        let mut pp = Self::default();
+        pp.extend(v);
         pp
     }
 }
 
-impl<T> Default for Peapod<T>
+impl<T, W, O> Default for Peapod<T, Global, W, O>
 where
     T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
 {
     fn default() -> Self {
-        Self::new()
+        Self::from_parts(TagVec::new_in(Global), AllocVec::new_in(Global))
     }
 }
 
-impl<T> IntoIterator for Peapod<T>
+impl<T, A, W, O> IntoIterator for Peapod<T, A, W, O>
 where
     T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
 {
     type Item = T;
 
-    type IntoIter = IntoIter<T>;
+    type IntoIter = IntoIter<T, A, W, O>;
 
     fn into_iter(self) -> Self::IntoIter {
         let (tags, data) = self.cleave();
@@ -270,22 +941,67 @@ where
             tags,
             data,
             index: 0,
+            _marker: PhantomData,
         }
     }
 }
 
-pub struct IntoIter<T>
+impl<'a, T, A, W, O> IntoIterator for &'a Peapod<T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Item = PeaRef<'a, T>;
+
+    type IntoIter = slice::Iter<'a, T, W, O>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct IntoIter<T, A = Global, W = usize, O = Lsb0>
 where
     T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
 {
-    tags: BitVec,
-    data: Vec<T::Value>,
+    tags: TagVec<A, W, O>,
+    data: AllocVec<T::Value, A>,
     index: usize,
+    // See Peapod
+    _marker: PhantomData<*const T>,
+}
+
+// # Safety
+// An `IntoIter` owns the elements that are left, like `Peapod`
+unsafe impl<T, A, W, O> Send for IntoIter<T, A, W, O>
+where
+    T: Phenotype + Send,
+    A: Allocator + Send,
+    W: TagWord,
+    O: TagOrder,
+{
 }
 
-impl<T> Iterator for IntoIter<T>
+unsafe impl<T, A, W, O> Sync for IntoIter<T, A, W, O>
+where
+    T: Phenotype + Sync,
+    A: Allocator + Sync,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+
+impl<T, A, W, O> Iterator for IntoIter<T, A, W, O>
 where
     T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
 {
     type Item = T;
 
@@ -298,7 +1014,7 @@ where
             // We are reading the tag that matches the data
             let elem = unsafe {
                 Some(<T as Phenotype>::reknit(
-                    self.tags[self.index * T::BITS..(self.index + 1) * T::BITS].load(),
+                    self.tags.load(self.index * T::BITS, T::BITS),
                     // Read a value out of the vector
                     // # Safety
                     // We are reading from a valid ptr (as_ptr), and the offset is
@@ -319,9 +1035,12 @@ where
     }
 }
 
-impl<T> DoubleEndedIterator for IntoIter<T>
+impl<T, A, W, O> DoubleEndedIterator for IntoIter<T, A, W, O>
 where
     T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
 {
     fn next_back(&mut self) -> Option<Self::Item> {
         let len = self.data.len();
@@ -340,7 +1059,7 @@ where
             // The tag matches the data
             unsafe {
                 Some(<T as Phenotype>::reknit(
-                    self.tags[(len - 1) * T::BITS..len * T::BITS].load(),
+                    self.tags.load((len - 1) * T::BITS, T::BITS),
                     // Read a value out of the vector
                     // # Safety
                     // We are reading from a valid ptr (as_ptr), and the offset is
@@ -352,9 +1071,12 @@ where
     }
 }
 
-impl<T> ExactSizeIterator for IntoIter<T>
+impl<T, A, W, O> ExactSizeIterator for IntoIter<T, A, W, O>
 where
     T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
 {
     fn len(&self) -> usize {
         let (lower, upper) = self.size_hint();
@@ -367,9 +1089,12 @@ where
     }
 }
 
-impl<T> Drop for IntoIter<T>
+impl<T, A, W, O> Drop for IntoIter<T, A, W, O>
 where
     T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
 {
     fn drop(&mut self) {
         for _ in self {}
@@ -380,47 +1105,127 @@ where
     }
 }
 
-impl<T> Debug for Peapod<T>
+// `{:?}` shows the elements, like a `Vec`. `{:#?}` shows how many elements of
+// each variant there are instead, which is easier to read for a big `Peapod`
+impl<T, A, W, O> Debug for Peapod<T, A, W, O>
 where
-    T: Phenotype,
+    T: Phenotype + Debug + Frozen,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Peapod")
-            .field(
-                "tags",
-                &self
-                    .tags
-                    .chunks(T::BITS)
-                    .map(BitField::load::<usize>)
-                    .collect::<Vec<_>>(),
-            )
-            .field("data", &[..])
-            .finish()
+        if !f.alternate() {
+            return f.debug_list().entries(self.iter()).finish();
+        }
+
+        let mut counts = vec![0; T::NUM_VARIANTS];
+        for i in 0..self.len() {
+            counts[self.get_variant(i)] += 1;
+        }
+
+        let mut variants = f.debug_struct("Peapod");
+        for (info, count) in T::VARIANTS.iter().zip(&counts) {
+            variants.field(info.name, count);
+        }
+        variants.finish()
     }
 }
 
-impl<T> Display for Peapod<T>
+/// A breakdown of the memory a [`Peapod`] has allocated, returned by
+/// [`Peapod::memory_usage`]. All the sizes are in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The bytes taken up by tags, rounded up to whole words.
+    pub tag_bytes: usize,
+    /// The bytes taken up by the elements' data.
+    pub data_bytes: usize,
+    /// The bytes allocated for tags that aren't in use.
+    pub spare_tag_bytes: usize,
+    /// The bytes allocated for data that aren't in use.
+    pub spare_data_bytes: usize,
+}
+
+impl MemoryUsage {
+    /// Returns the total number of bytes allocated.
+    pub fn total(&self) -> usize {
+        self.used() + self.wasted()
+    }
+
+    /// Returns the number of bytes in use.
+    pub fn used(&self) -> usize {
+        self.tag_bytes + self.data_bytes
+    }
+
+    /// Returns the number of bytes allocated but not in use.
+    pub fn wasted(&self) -> usize {
+        self.spare_tag_bytes + self.spare_data_bytes
+    }
+}
+
+/// Debug formatting for a [`Peapod`] that shows how many elements of each
+/// variant it holds, using the names from [`PhenotypeDebug::debug_tag`].
+///
+/// This struct is created by [`Peapod::debug_variants`].
+pub struct DebugVariants<'a, T, A = Global, W = usize, O = Lsb0>
 where
     T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    pod: &'a Peapod<T, A, W, O>,
+}
+
+impl<T, A, W, O> Debug for DebugVariants<'_, T, A, W, O>
+where
+    T: PhenotypeDebug,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut counts = vec![0; T::NUM_VARIANTS];
+        for i in 0..self.pod.len() {
+            counts[self.pod.get_variant(i)] += 1;
+        }
+
+        let mut variants = f.debug_map();
+        for (tag, count) in counts.iter().enumerate() {
+            // format_args! so the names aren't printed with quotes
+            variants.entry(&format_args!("{}", T::debug_tag(tag)), count);
+        }
+        variants.finish()
+    }
+}
+
+impl<T, A, W, O> Display for Peapod<T, A, W, O>
+where
+    T: Phenotype + Display + Frozen,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str("[")?;
-        for i in 0..self.len() {
-            f.write_str(" ")?;
-            f.write_str(&format!("{{ tag: {}, data: .. }}", self.get_tag(i)))?;
-            f.write_str(",")?;
+        for (i, elem) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            Display::fmt(&*elem, f)?;
         }
-        f.write_str(" ")?;
-        f.write_str("]")?;
-        Ok(())
+        f.write_str("]")
     }
 }
 
-impl<T> Extend<T> for Peapod<T>
+impl<T, A, W, O> Extend<T> for Peapod<T, A, W, O>
 where
     T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
 {
-    fn extend<A: IntoIterator<Item = T>>(&mut self, iter: A) {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
         // If we can, reserve space ahead of time
         let iter = iter.into_iter();
         if let (_, Some(len)) = iter.size_hint() {
@@ -428,52 +1233,199 @@ where
         } else if let (len, None) = iter.size_hint() {
             self.reserve(len);
         }
+        let mut writer = TagWriter::new(self);
         for elem in iter {
-            self.push(elem);
+            writer.push(elem);
+        }
+    }
+}
+
+// Pushes elements to a `Peapod`, buffering their tags so they can be written
+// a word at a time instead of one by one.
+//
+// The buffered tags are written when the writer is dropped, so the tags still
+// match up with the data if the source iterator panics.
+struct TagWriter<'a, T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    pod: &'a mut Peapod<T, A, W, O>,
+    word: usize,
+    buffered: usize,
+}
+
+impl<'a, T, A, W, O> TagWriter<'a, T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    // How many tags fit in a word
+    const PER_WORD: usize = match T::BITS {
+        // Enums stored whole have no tags, so there's nothing to buffer
+        0 => usize::BITS as usize,
+        bits => usize::BITS as usize / bits,
+    };
+
+    fn new(pod: &'a mut Peapod<T, A, W, O>) -> Self {
+        // Get rid of any cruft so the new tags end up right after the last one,
+        // see https://github.com/fprasx/peapod/issues/2
+        pod.tags.truncate(pod.data.len() * T::BITS);
+        Self {
+            pod,
+            word: 0,
+            buffered: 0,
+        }
+    }
+
+    fn push(&mut self, t: T) {
+        if self.buffered == 0 {
+            // Make room for the whole word now, so flushing never allocates,
+            // and can't panic while we're in drop
+            self.pod.tags.reserve(Self::PER_WORD * T::BITS);
+        }
+
+        let (tag, data) = t.cleave();
+        // If this panics, the data and tag are both left out
+        if let Err(data) = self.pod.data.push_within_capacity(data) {
+            Self::grow_and_push(&mut self.pod.data, data);
+        }
+
+        self.word = O::pack_tag(self.word, self.buffered, tag, T::BITS);
+        self.buffered += 1;
+        if self.buffered == Self::PER_WORD {
+            self.flush();
         }
     }
+
+    // **Note**: `Vec::push` from allocator-api2 inlines the code that grows the
+    // buffer, which made `extend` about 50% slower, so we keep it out of line
+    #[cold]
+    #[inline(never)]
+    fn grow_and_push(data: &mut AllocVec<T::Value, A>, value: T::Value) {
+        data.push(value);
+    }
+
+    fn flush(&mut self) {
+        self.pod.tags.push_bits(self.word, self.buffered * T::BITS);
+        self.word = 0;
+        self.buffered = 0;
+    }
 }
 
-impl<T> FromIterator<T> for Peapod<T>
+impl<T, A, W, O> Drop for TagWriter<'_, T, A, W, O>
 where
     T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+impl<T, W, O> FromIterator<T> for Peapod<T, Global, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
 {
     fn from_iter<A: IntoIterator<Item = T>>(iter: A) -> Self {
-        let mut pp = Peapod::<T>::new();
+        let mut pp = Self::default();
         pp.extend(iter);
         pp
     }
 }
 
-impl<T> PartialEq for Peapod<T>
+// **Note**: the comparisons go through reknit copies rather than `T::Value`,
+// as comparing the unions directly would look at padding and inactive fields
+
+impl<T, A, W, O> PartialEq for Peapod<T, A, W, O>
 where
-    T: Phenotype,
-    T::Value: PartialEq,
+    T: Phenotype + PartialEq + Frozen,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
 {
     fn eq(&self, other: &Self) -> bool {
-        self.tags == other.tags && self.data == other.data
+        self.len() == other.len()
+            && (0..self.len()).all(|i| {
+                // Checking the tags first means we only reknit matching variants
+                self.get_tag(i) == other.get_tag(i) && *self.view(i) == *other.view(i)
+            })
+    }
+}
+
+impl<T, A, W, O> Eq for Peapod<T, A, W, O>
+where
+    T: Phenotype + Eq + Frozen,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+
+impl<T, A, W, O> PartialOrd for Peapod<T, A, W, O>
+where
+    T: Phenotype + PartialOrd + Frozen,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T, A, W, O> Ord for Peapod<T, A, W, O>
+where
+    T: Phenotype + Ord + Frozen,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<T, A, W, O> Hash for Peapod<T, A, W, O>
+where
+    T: Phenotype + Hash + Frozen,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Hash the length like slices do, so `[a, b], [c]` and `[a], [b, c]` differ
+        state.write_usize(self.len());
+        self.iter().for_each(|t| t.hash(state));
     }
 }
 
-impl<T> Clone for Peapod<T>
+impl<T, A, W, O> Clone for Peapod<T, A, W, O>
 where
     T: Phenotype,
     T::Value: Clone,
+    A: Allocator + Clone,
+    W: TagWord,
+    O: TagOrder,
 {
     fn clone(&self) -> Self {
-        Self {
-            tags: self.tags.clone(),
-            data: self.data.clone(),
-        }
+        Self::from_parts(self.tags.clone(), self.data.clone())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::peapod;
     use core::iter::{DoubleEndedIterator, Iterator};
-    use phenotype_macro::Phenotype;
+    use phenotype_macro::{Phenotype, PhenotypeDebug};
 
     #[derive(Phenotype, PartialEq, Debug)]
     enum TestData {
@@ -572,4 +1524,805 @@ mod tests {
         pp.clear();
         assert_eq!(pp.len(), 0);
     }
+
+    #[test]
+    fn insert_remove_shift() {
+        let mut pp = peapod![TestData::C, TestData::B(2, 2.0)];
+        pp.insert(0, TestData::A { u: 0, f: 0.0 });
+        pp.insert(3, TestData::C);
+        pp.insert(2, TestData::B(1, 1.0));
+        assert_eq!(pp.remove(1), TestData::C);
+        assert_eq!(
+            Vec::from(pp),
+            [
+                TestData::A { u: 0, f: 0.0 },
+                TestData::B(1, 1.0),
+                TestData::B(2, 2.0),
+                TestData::C
+            ]
+        );
+    }
+
+    #[test]
+    fn split_off_append_round_trip() {
+        let mut pp = peapod![
+            TestData::A { u: 1, f: 1.0 },
+            TestData::B(2, 2.0),
+            TestData::C,
+            TestData::A { u: 4, f: 4.0 },
+            TestData::B(5, 5.0)
+        ];
+        let mut tail = pp.split_off(2);
+        assert_eq!(pp.len(), 2);
+        assert_eq!(tail.len(), 3);
+        assert_eq!(*tail.get(0).unwrap(), TestData::C);
+
+        pp.append(&mut tail);
+        assert!(tail.is_empty());
+        assert_eq!(pp.len(), 5);
+        assert_eq!(pp.pop(), Some(TestData::B(5, 5.0)));
+        assert_eq!(*pp.get(2).unwrap(), TestData::C);
+    }
+
+    #[test]
+    fn concat_keeps_order() {
+        let batches = (0..4).map(|i| peapod![TestData::B(i, 0.0), TestData::C]);
+        let pp = Peapod::concat(batches);
+        assert_eq!(pp.len(), 8);
+        assert_eq!(
+            Vec::from(pp)
+                .into_iter()
+                .filter(|t| *t != TestData::C)
+                .collect::<Vec<_>>(),
+            (0..4).map(|i| TestData::B(i, 0.0)).collect::<Vec<_>>()
+        );
+        assert!(Peapod::<TestData>::concat([]).is_empty());
+    }
+
+    #[test]
+    fn extend_matches_push() {
+        let elems = || {
+            (0..100).map(|i| match i % 3 {
+                0 => TestData::A { u: i, f: 0.5 },
+                1 => TestData::B(i, 1.5),
+                _ => TestData::C,
+            })
+        };
+        let mut pushed = Peapod::new();
+        for elem in elems() {
+            pushed.push(elem);
+        }
+        // Start from a partially filled collection so tags don't start on a word
+        let mut extended = peapod![TestData::C];
+        extended.extend(elems().filter(|_| true));
+        extended.remove(0);
+        assert_eq!(Vec::from(extended), Vec::from(pushed));
+        assert_eq!(
+            Vec::from(Peapod::from_trusted_len(elems())),
+            elems().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn extend_panic_keeps_tags_matched() {
+        extern crate std;
+        let mut pp = peapod![TestData::C];
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            pp.extend((0..10).map(|i| match i {
+                7 => panic!("iterator failed"),
+                _ => TestData::B(i, 0.0),
+            }))
+        }));
+        assert!(result.is_err());
+        assert_eq!(pp.len(), 8);
+        assert_eq!(*pp.get(7).unwrap(), TestData::B(6, 0.0));
+        assert_eq!(pp.pop(), Some(TestData::B(6, 0.0)));
+    }
+
+    #[test]
+    fn send_and_sync_follow_t() {
+        fn send_sync<S: Send + Sync>() {}
+        send_sync::<Peapod<TestData>>();
+        send_sync::<IntoIter<TestData, Global>>();
+        send_sync::<PeaRef<'_, TestData>>();
+        send_sync::<crate::PeaSlice<'_, TestData>>();
+        send_sync::<crate::PeaSliceMut<'_, TestData>>();
+        send_sync::<crate::SparsePeapod<TestData>>();
+        send_sync::<crate::RlePeapod<TestData>>();
+        send_sync::<crate::ConcurrentPeapod<TestData>>();
+        send_sync::<crate::SegmentedPeapod<TestData>>();
+    }
+
+    #[test]
+    fn eq_compares_values() {
+        extern crate std;
+        use std::{collections::hash_map::DefaultHasher, hash::BuildHasher};
+
+        #[derive(Phenotype, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+        enum Small {
+            Byte(u8),
+            Word(u64),
+        }
+
+        // The first pod has a stale `Word` in the union's inactive bytes
+        let mut a = peapod![Small::Word(u64::MAX)];
+        a.pop();
+        a.push(Small::Byte(1));
+        let b = peapod![Small::Byte(1)];
+        assert_eq!(a, b);
+
+        let hash = |pp: &Peapod<Small>| {
+            core::hash::BuildHasherDefault::<DefaultHasher>::default().hash_one(pp)
+        };
+        assert_eq!(hash(&a), hash(&b));
+
+        a.push(Small::Word(0));
+        assert_ne!(a, b);
+        assert!(b < a);
+        assert!(peapod![Small::Word(0)] > a);
+    }
+
+    #[test]
+    fn dedup_keeps_first_of_runs() {
+        let mut pp = peapod![
+            TestData::C,
+            TestData::C,
+            TestData::B(1, 0.0),
+            TestData::B(1, 0.0),
+            TestData::B(2, 0.0),
+            TestData::C
+        ];
+        pp.dedup();
+        assert_eq!(
+            Vec::from(pp),
+            [
+                TestData::C,
+                TestData::B(1, 0.0),
+                TestData::B(2, 0.0),
+                TestData::C
+            ]
+        );
+
+        let mut pp = peapod![
+            TestData::A { u: 1, f: 1.0 },
+            TestData::A { u: 2, f: 2.0 },
+            TestData::B(3, 3.0),
+            TestData::C
+        ];
+        pp.dedup_by_key(|t| matches!(t, TestData::C));
+        assert_eq!(Vec::from(pp), [TestData::A { u: 1, f: 1.0 }, TestData::C]);
+    }
+
+    #[test]
+    fn dedup_by_panic_leaves_pod_alone() {
+        extern crate std;
+        let mut pp = peapod![TestData::C, TestData::C, TestData::B(1, 0.0)];
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            pp.dedup_by(|a, _| match a {
+                TestData::B(..) => panic!("comparison failed"),
+                _ => true,
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(
+            Vec::from(pp),
+            [TestData::C, TestData::C, TestData::B(1, 0.0)]
+        );
+    }
+
+    #[test]
+    fn debug_shows_values() {
+        use crate::PhenotypeDebug;
+        use alloc::format;
+
+        let pp = peapod![TestData::B(1, 2.0), TestData::C];
+        assert_eq!(format!("{pp:?}"), "[B(1, 2.0), C]");
+        assert_eq!(
+            format!("{pp:#?}"),
+            "Peapod {\n    A: 0,\n    B: 1,\n    C: 1,\n}"
+        );
+
+        #[derive(Phenotype, PhenotypeDebug)]
+        enum Event {
+            Open(u32),
+            Close(u32),
+            Tick,
+        }
+        let pp = peapod![Event::Tick, Event::Open(1), Event::Tick];
+        assert_eq!(
+            format!("{:?}", pp.debug_variants()),
+            "{Event::Open: 1, Event::Close: 0, Event::Tick: 2}"
+        );
+        assert_eq!(
+            format!("{:#?}", pp.debug_variants()),
+            "{\n    Event::Open: 1,\n    Event::Close: 0,\n    Event::Tick: 2,\n}"
+        );
+    }
+
+    #[test]
+    fn display_shows_values() {
+        use alloc::format;
+
+        #[derive(Phenotype)]
+        enum Event {
+            Open(u32),
+            Tick,
+        }
+
+        impl Display for Event {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    Event::Open(id) => write!(f, "open {id}"),
+                    Event::Tick => f.write_str("tick"),
+                }
+            }
+        }
+
+        let mut pp = Peapod::new();
+        assert_eq!(format!("{pp}"), "[]");
+        pp.extend([Event::Tick, Event::Open(1), Event::Tick]);
+        assert_eq!(format!("{pp}"), "[tick, open 1, tick]");
+    }
+
+    #[test]
+    fn try_reserve_errors_leave_pod_alone() {
+        let mut pp = peapod![TestData::C, TestData::B(1, 1.0)];
+        assert_eq!(
+            pp.try_reserve(usize::MAX),
+            Err(PeapodError::CapacityOverflow)
+        );
+        assert_eq!(
+            pp.try_reserve(usize::MAX / 2),
+            Err(PeapodError::CapacityOverflow)
+        );
+        assert_eq!(
+            pp.try_extend((0..).map(|i| TestData::B(i, 0.0)).take(usize::MAX)),
+            Err(PeapodError::CapacityOverflow)
+        );
+        assert_eq!(Vec::from(pp), [TestData::C, TestData::B(1, 1.0)]);
+        assert!(Peapod::<TestData>::try_with_capacity(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn try_push_and_extend() {
+        let mut pp = Peapod::try_with_capacity(3).unwrap();
+        pp.try_push(TestData::C).unwrap();
+        pp.try_extend([TestData::B(1, 1.0), TestData::A { u: 2, f: 2.0 }])
+            .unwrap();
+        pp.try_extend((0..5).map(|i| TestData::B(i, 0.0)).filter(|_| true))
+            .unwrap();
+        assert_eq!(pp.len(), 8);
+        assert_eq!(pp.pop(), Some(TestData::B(4, 0.0)));
+        assert_eq!(*pp.get(2).unwrap(), TestData::A { u: 2, f: 2.0 });
+    }
+
+    #[test]
+    fn failed_try_extend_drops_appended() {
+        use alloc::rc::Rc;
+        use allocator_api2::alloc::{AllocError, Layout};
+        use core::{cell::Cell, ptr::NonNull};
+
+        // Fails once it has made `budget` allocations
+        #[derive(Clone, Copy)]
+        struct Limited<'a>(&'a Cell<usize>);
+
+        unsafe impl Allocator for Limited<'_> {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                let budget = self.0.get().checked_sub(1).ok_or(AllocError)?;
+                self.0.set(budget);
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                Global.deallocate(ptr, layout)
+            }
+        }
+
+        #[derive(Phenotype)]
+        enum Shared {
+            Rc(Rc<u8>),
+            Empty,
+        }
+
+        let rc = Rc::new(0);
+        // Enough for the first tags and data, but not for growing the data
+        let budget = Cell::new(2);
+        let mut pp = Peapod::new_in(Limited(&budget));
+        pp.push(Shared::Rc(rc.clone()));
+        // No size hint, so the elements are pushed until the data has to grow
+        let result = pp.try_extend((0..100).map(|_| Shared::Rc(rc.clone())).filter(|_| true));
+        assert_eq!(result, Err(PeapodError::AllocError));
+        assert_eq!(pp.len(), 1);
+        // The element that failed to push and the ones rolled back are gone
+        assert_eq!(Rc::strong_count(&rc), 2);
+        assert!(matches!(pp.pop(), Some(Shared::Rc(_))));
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn allocates_through_allocator() {
+        use allocator_api2::alloc::{AllocError, Layout};
+        use core::{cell::Cell, ptr::NonNull};
+
+        // Counts the live allocations made through it
+        #[derive(Clone, Copy)]
+        struct Counting<'a>(&'a Cell<isize>);
+
+        unsafe impl Allocator for Counting<'_> {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.set(self.0.get() + 1);
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                self.0.set(self.0.get() - 1);
+                Global.deallocate(ptr, layout)
+            }
+        }
+
+        let live = Cell::new(0);
+        let mut pp = Peapod::new_in(Counting(&live));
+        assert_eq!(live.get(), 0);
+        pp.extend([TestData::C, TestData::B(1, 1.0), TestData::C]);
+        // One allocation for the tags and one for the data
+        assert_eq!(live.get(), 2);
+
+        let tail = pp.split_off(1);
+        assert_eq!(live.get(), 4);
+        assert_eq!(Vec::from(tail), [TestData::B(1, 1.0), TestData::C]);
+        assert_eq!(live.get(), 2);
+
+        pp.append(&mut peapod![TestData::A { u: 2, f: 2.0 }]);
+        assert_eq!(pp.pop(), Some(TestData::A { u: 2, f: 2.0 }));
+        drop(pp);
+        assert_eq!(live.get(), 0);
+    }
+
+    #[test]
+    fn shrink_gives_memory_back() {
+        let mut pp: Peapod<TestData> = (0..1000).map(|i| TestData::B(i, 0.0)).collect();
+        pp.truncate(10);
+        let before = pp.memory_usage();
+        assert_eq!(
+            before.data_bytes,
+            10 * mem::size_of::<<TestData as Phenotype>::Value>()
+        );
+        assert!(before.wasted() > 0);
+
+        pp.shrink_to(20);
+        assert!(pp.capacity() >= 20);
+        assert!(pp.memory_usage().total() < before.total());
+
+        pp.shrink_to_fit();
+        let after = pp.memory_usage();
+        assert_eq!(after.spare_data_bytes, 0);
+        assert_eq!(after.spare_tag_bytes, 0);
+        assert_eq!(after.used(), before.used());
+        assert_eq!(pp.len(), 10);
+        assert_eq!(pp.pop(), Some(TestData::B(9, 0.0)));
+
+        pp.clear();
+        pp.shrink_to_fit();
+        assert_eq!(pp.memory_usage().total(), 0);
+
+        pp.reserve_exact(100);
+        assert_eq!(
+            pp.memory_usage().spare_data_bytes,
+            100 * mem::size_of::<<TestData as Phenotype>::Value>()
+        );
+    }
+
+    #[test]
+    fn tag_layouts_are_fixed() {
+        // Tags 2, 0, 1, 2, 1 at two bits each
+        let elems = || {
+            [
+                TestData::C,
+                TestData::A { u: 1, f: 1.0 },
+                TestData::B(2, 2.0),
+                TestData::C,
+                TestData::B(3, 3.0),
+            ]
+        };
+
+        let lsb: Peapod<TestData, Global, u8, Lsb0> = elems().into_iter().collect();
+        assert_eq!(lsb.tag_words(), [0b10_01_00_10, 0b01]);
+        let mut pushed = Peapod::<TestData, Global, u8, Lsb0>::default();
+        elems().into_iter().for_each(|t| pushed.push(t));
+        assert_eq!(pushed.tag_bits(), lsb.tag_bits());
+
+        // Tags straddle the word boundary with u8s, but the order stays the same
+        let mut msb: Peapod<TestData, Global, u8, Msb0> = elems().into_iter().collect();
+        assert_eq!(msb.tag_words(), [0b10_00_01_10, 0b01 << 6]);
+        msb.insert(1, TestData::B(0, 0.0));
+        assert_eq!(msb.remove(0), TestData::C);
+        msb.sort_by_tag();
+        assert_eq!(
+            (0..msb.len())
+                .map(|i| msb.tag(i).unwrap())
+                .collect::<Vec<_>>(),
+            [0, 1, 1, 1, 2]
+        );
+
+        let wide: Peapod<TestData, Global, u16, Msb0> = elems().into_iter().collect();
+        assert_eq!(wide.tag_words(), [0b10_00_01_10_01 << 6]);
+        assert_eq!(Vec::from(wide), elems());
+    }
+
+    #[test]
+    fn aligned_tags() {
+        #[derive(Phenotype, PartialEq, Debug)]
+        #[phenotype(tag_align = "nibble")]
+        enum Nibble {
+            A(u8),
+            B,
+            C,
+        }
+
+        #[derive(Phenotype, PartialEq, Debug)]
+        #[phenotype(tag_align = "byte")]
+        enum Byte {
+            A(u8),
+            B,
+            C,
+        }
+
+        assert_eq!(Nibble::BITS, 4);
+        assert_eq!(Byte::BITS, 8);
+
+        let nibbles: Peapod<Nibble, Global, u8, Lsb0> =
+            [Nibble::C, Nibble::A(1), Nibble::B].into_iter().collect();
+        assert_eq!(nibbles.tag_words(), [0x02, 0x01]);
+        assert_eq!(Vec::from(nibbles), [Nibble::C, Nibble::A(1), Nibble::B]);
+
+        let mut bytes: Peapod<Byte> = peapod![Byte::B, Byte::C, Byte::A(7)];
+        bytes.insert(1, Byte::A(3));
+        assert_eq!(
+            (0..4).map(|i| bytes.tag(i).unwrap()).collect::<Vec<_>>(),
+            [1, 0, 2, 0]
+        );
+        // One tag per byte of the word
+        assert_eq!(bytes.tag_words()[0].to_le_bytes()[..4], [1, 0, 2, 0]);
+    }
+
+    #[test]
+    fn over_aligned_fields() {
+        extern crate alloc;
+        use crate::test_util::{wide, Simd};
+        use alloc::boxed::Box;
+
+        wide!();
+
+        let items = || {
+            [
+                Wide::Int(1, u128::MAX - 1),
+                Wide::Empty,
+                Wide::lanes(3),
+                Wide::Boxed(Box::new(Simd([5; 4])), u128::MAX),
+                Wide::Int(2, 1 << 100),
+            ]
+        };
+        // The union is packed, so the data is stored unaligned
+        assert_eq!(mem::align_of::<<Wide<u128> as Phenotype>::Value>(), 1);
+        assert_eq!(Wide::<u128>::VARIANTS[1].align, 32);
+
+        let mut pp: Peapod<Wide<u128>> = items().into_iter().collect();
+        for (i, item) in items().iter().enumerate() {
+            assert_eq!(pp.get(i).as_deref(), Some(item));
+        }
+        assert!(pp.iter().map(|item| item.clone()).eq(items()));
+
+        assert_eq!(pp.pop(), items().into_iter().last());
+        pp.remove(1);
+        pp.insert(0, Wide::Empty);
+        let mut expected = Vec::from(items());
+        expected.pop();
+        expected.remove(1);
+        expected.insert(0, Wide::Empty);
+        // Moving out runs over the unaligned data, as does dropping what's left
+        let mut iter = pp.into_iter();
+        assert_eq!(iter.next(), Some(expected.remove(0)));
+        assert_eq!(iter.next_back(), expected.pop());
+    }
+
+    #[test]
+    fn over_aligned_moves() {
+        extern crate alloc;
+        use crate::test_util::Simd;
+        use alloc::string::{String, ToString};
+
+        // Every way of moving elements around, for each layout. The `String`s
+        // make Miri catch anything that is leaked or dropped twice.
+        macro_rules! moves {
+            ($name:ident, $layout:literal) => {{
+                #[derive(Phenotype, PartialEq, Debug, Clone)]
+                #[phenotype(layout = $layout)]
+                enum $name {
+                    Lanes(Simd, String),
+                    Byte(u8),
+                    Empty,
+                }
+
+                let item = |i: u64| match i % 3 {
+                    0 => $name::Lanes(Simd([i; 4]), i.to_string()),
+                    1 => $name::Byte(i as u8),
+                    _ => $name::Empty,
+                };
+                let mut pp: Peapod<$name> = (0..9).map(item).collect();
+                let mut expected: Vec<_> = (0..9).map(item).collect();
+
+                pp.insert(2, item(9));
+                expected.insert(2, item(9));
+                assert_eq!(pp.remove(4), expected.remove(4));
+
+                let mut other: Peapod<$name> = (10..14).map(item).collect();
+                pp.append(&mut other);
+                expected.extend((10..14).map(item));
+                assert!(other.is_empty());
+                let tail = pp.split_off(7);
+                let expected_tail = expected.split_off(7);
+
+                let mut slice = pp.as_mut_slice();
+                assert_eq!(slice.replace(1, item(15)), expected[1]);
+                expected[1] = item(15);
+                slice.swap(0, 6);
+                expected.swap(0, 6);
+                slice.slice_mut(2..).reverse();
+                expected[2..].reverse();
+
+                assert_eq!(Vec::from(pp), expected);
+                // Dropping a half-consumed iterator drops the rest
+                let mut iter = tail.into_iter();
+                assert_eq!(iter.next(), Some(expected_tail[0].clone()));
+                assert_eq!(iter.next_back(), expected_tail.last().cloned());
+            }};
+        }
+        moves!(Packed, "packed");
+        moves!(Aligned, "aligned");
+        moves!(Auto, "auto");
+    }
+
+    #[test]
+    fn payload_layouts() {
+        macro_rules! mixed {
+            ($name:ident, $layout:literal) => {
+                #[derive(Phenotype, PartialEq, Debug)]
+                #[phenotype(layout = $layout)]
+                enum $name {
+                    A(u8, u32),
+                    B { b: u16 },
+                    C,
+                }
+            };
+        }
+        mixed!(Packed, "packed");
+        mixed!(Aligned, "aligned");
+        mixed!(Auto, "auto");
+
+        // Aligning `A` pads it from 5 bytes to 8, so `auto` packs it
+        assert_eq!(mem::size_of::<<Packed as Phenotype>::Value>(), 5);
+        assert_eq!(mem::align_of::<<Packed as Phenotype>::Value>(), 1);
+        assert_eq!(mem::size_of::<<Aligned as Phenotype>::Value>(), 8);
+        assert_eq!(mem::align_of::<<Aligned as Phenotype>::Value>(), 4);
+        assert_eq!(mem::align_of::<<Auto as Phenotype>::Value>(), 1);
+        assert_eq!(Packed::PEAPOD_SIZE, Some(6));
+        assert_eq!(Aligned::PEAPOD_SIZE, Some(9));
+        assert_eq!(Auto::PEAPOD_SIZE, Some(6));
+        assert_eq!(Aligned::PAYLOAD_SIZES, &[8, 2, 0]);
+
+        // Aligning `Even` costs nothing, so `auto` aligns it
+        #[derive(Phenotype, PartialEq, Debug)]
+        #[phenotype(layout = "auto")]
+        enum Even {
+            A(u32, u32),
+            B(u64),
+            C { c: [u8; 3] },
+        }
+        assert_eq!(mem::size_of::<<Even as Phenotype>::Value>(), 8);
+        assert_eq!(mem::align_of::<<Even as Phenotype>::Value>(), 8);
+
+        // Sizes aren't known, so generic enums are always packed
+        #[derive(Phenotype, PartialEq, Debug)]
+        #[phenotype(layout = "auto")]
+        enum Generic<T> {
+            A(T),
+            B,
+        }
+        assert_eq!(mem::align_of::<<Generic<u64> as Phenotype>::Value>(), 1);
+
+        let aligned = peapod![Aligned::C, Aligned::A(1, 2), Aligned::B { b: 3 }];
+        assert_eq!(
+            Vec::from(aligned),
+            [Aligned::C, Aligned::A(1, 2), Aligned::B { b: 3 }]
+        );
+        let even = peapod![Even::B(u64::MAX), Even::C { c: [4; 3] }, Even::A(5, 6)];
+        assert_eq!(
+            Vec::from(even),
+            [Even::B(u64::MAX), Even::C { c: [4; 3] }, Even::A(5, 6)]
+        );
+        let generic = peapod![Generic::A(7u64), Generic::B];
+        assert_eq!(Vec::from(generic), [Generic::A(7), Generic::B]);
+    }
+
+    #[test]
+    fn pass_through() {
+        use core::num::NonZeroU32;
+
+        // rustc hides these tags in a niche, so they're stored whole
+        assert_eq!(<Option<&u8>>::BITS, 0);
+        assert_eq!(<Option<NonZeroU32>>::PEAPOD_SIZE, Some(4));
+        let (a, b) = (1u8, 2u8);
+        let mut refs: Peapod<Option<&u8>> = peapod![Some(&a), None];
+        refs.push(Some(&b));
+        refs.insert(1, None);
+        assert_eq!(refs.tag_words(), []);
+        assert_eq!(refs.tag(2), Some(0));
+        assert_eq!(refs.get(3).as_deref(), Some(&Some(&b)));
+        assert_eq!(refs.remove(0), Some(&a));
+        assert_eq!(refs.pop(), Some(Some(&b)));
+        assert_eq!(Vec::from(refs), [None, None]);
+
+        let mut ints: Peapod<Option<NonZeroU32>> = (0..100).map(NonZeroU32::new).collect();
+        assert!(ints.capacity() >= 100);
+        assert_eq!(ints.iter().filter(|int| int.is_none()).count(), 1);
+        assert_eq!(ints.pop(), Some(NonZeroU32::new(99)));
+        assert!(ints.into_iter().eq((0..99).map(NonZeroU32::new)));
+
+        #[derive(Phenotype, PhenotypeDebug, PartialEq, Debug)]
+        #[phenotype(pass_through = "always")]
+        enum Always {
+            A(u32),
+            B,
+        }
+        // A niche: the tag fits in the reference's null value
+        #[derive(Phenotype, PartialEq, Debug)]
+        #[phenotype(pass_through = "auto")]
+        enum Niche {
+            A(&'static u8),
+            B,
+        }
+        // No niche: splitting saves the padding after the tag
+        #[derive(Phenotype, PhenotypeDebug, PartialEq, Debug)]
+        #[phenotype(pass_through = "auto")]
+        enum Compact {
+            A(u32),
+            B,
+        }
+        assert_eq!(Always::BITS, 0);
+        assert_eq!(Always::PEAPOD_SIZE, Some(8));
+        assert_eq!(Always::VARIANTS[0].name, "Always");
+        assert_eq!(Always::debug_tag(0), "Always");
+        assert_eq!(Niche::BITS, 0);
+        assert_eq!(Niche::NUM_VARIANTS, 1);
+        assert_eq!(Compact::BITS, 1);
+        assert_eq!(Compact::PEAPOD_SIZE, Some(5));
+        assert_eq!(Compact::debug_tag(1), "Compact::B");
+
+        let always = peapod![Always::B, Always::A(3)];
+        assert_eq!(Vec::from(always), [Always::B, Always::A(3)]);
+        static C: u8 = 4;
+        let niche = peapod![Niche::A(&C), Niche::B];
+        assert_eq!(niche.tag_words(), []);
+        assert_eq!(Vec::from(niche), [Niche::A(&C), Niche::B]);
+        let compact = peapod![Compact::B, Compact::A(5)];
+        assert_eq!(Vec::from(compact), [Compact::B, Compact::A(5)]);
+    }
+
+    #[test]
+    fn inline_fields() {
+        extern crate alloc;
+        use alloc::string::String;
+
+        #[derive(Phenotype, PhenotypeDebug, PartialEq, Debug)]
+        enum Level {
+            Debug,
+            Info,
+            Warn,
+            Custom(#[phenotype(bits = 3)] u8),
+        }
+
+        #[derive(Phenotype, PartialEq, Debug)]
+        enum Cmd {
+            Move(#[phenotype(bits = 1)] bool, #[phenotype(bits = 1)] bool),
+            Turn {
+                #[phenotype(bits = 4)]
+                degrees: i8,
+            },
+            Say(String),
+            Repeat(#[phenotype(bits = 2)] u8, u32),
+        }
+
+        // Every payload fits in the tag, so there's no data at all
+        assert_eq!(Level::BITS, 5);
+        assert_eq!(Level::VARIANT_BITS, 2);
+        assert_eq!(Level::HAS_DATA, &[false; 4]);
+        assert_eq!(mem::size_of::<<Level as Phenotype>::Value>(), 0);
+        assert_eq!(Level::VARIANTS[3].inline_bits, 3);
+        let mut levels = peapod![Level::Warn, Level::Custom(5), Level::Custom(7)];
+        levels.insert(0, Level::Custom(0));
+        assert_eq!(levels.tag(1), Some(2));
+        assert_eq!(levels.tag(2), Some(3));
+        // The variant is in the low bits, and the payload above it
+        assert_eq!(
+            levels.tag_words(),
+            [3 | 2 << 5 | (3 | 5 << 2) << 10 | (3 | 7 << 2) << 15]
+        );
+        assert_eq!(
+            alloc::format!("{:?}", levels.debug_variants()),
+            "{Level::Debug: 0, Level::Info: 0, Level::Warn: 1, Level::Custom: 3}"
+        );
+        assert_eq!(levels.remove(2), Level::Custom(5));
+        assert_eq!(levels.memory_usage().data_bytes, 0);
+        assert_eq!(
+            Vec::from(levels),
+            [Level::Custom(0), Level::Warn, Level::Custom(7)]
+        );
+
+        // Fields packed into the tag are left out of the data
+        assert_eq!(Cmd::BITS, 6);
+        assert_eq!(Cmd::HAS_DATA, &[false, false, true, true]);
+        assert_eq!(Cmd::PAYLOAD_SIZES[3], 4);
+        let cmds = || {
+            [
+                Cmd::Move(true, false),
+                Cmd::Turn { degrees: -8 },
+                Cmd::Say(String::from("hi")),
+                Cmd::Repeat(3, 1 << 20),
+                Cmd::Turn { degrees: 7 },
+                Cmd::Move(false, true),
+            ]
+        };
+        let mut pp: Peapod<Cmd> = cmds().into_iter().collect();
+        for (i, cmd) in cmds().iter().enumerate() {
+            assert_eq!(pp.get(i).as_deref(), Some(cmd));
+        }
+        assert_eq!(
+            (0..6).map(|i| pp.tag(i).unwrap()).collect::<Vec<_>>(),
+            [0, 1, 2, 3, 1, 0]
+        );
+        pp.sort_by_tag();
+        assert_eq!(pp.get(2).as_deref(), Some(&Cmd::Turn { degrees: -8 }));
+        assert_eq!(pp.pop(), Some(Cmd::Repeat(3, 1 << 20)));
+        assert_eq!(pp.into_iter().count(), 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "`Small::Signed.0` doesn't fit in 4 bits")]
+    fn inline_fields_must_fit() {
+        #[derive(Phenotype, Debug)]
+        enum Small {
+            Signed(#[phenotype(bits = 4)] i32),
+            Unsigned(#[phenotype(bits = 4)] u32),
+        }
+        let mut pp = peapod![Small::Signed(-8), Small::Unsigned(15)];
+        pp.push(Small::Signed(8));
+    }
+
+    #[test]
+    fn inline_fields_checked_before_cleaving() {
+        extern crate std;
+        use alloc::rc::Rc;
+        use std::panic::{self, AssertUnwindSafe};
+
+        #[derive(Phenotype, Debug)]
+        enum Named {
+            Id {
+                #[phenotype(bits = 2)]
+                id: u8,
+                owner: Rc<()>,
+            },
+        }
+        let owner = Rc::new(());
+        let mut pp = Peapod::new();
+        let pushed = panic::catch_unwind(AssertUnwindSafe(|| {
+            pp.push(Named::Id {
+                id: 4,
+                owner: owner.clone(),
+            })
+        }));
+        assert!(pushed.is_err());
+        assert!(pp.is_empty());
+        // The element that didn't fit was dropped, not leaked
+        assert_eq!(Rc::strong_count(&owner), 1);
+    }
 }
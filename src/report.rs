@@ -0,0 +1,392 @@
+extern crate alloc;
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    any,
+    fmt::{self, Display, Write},
+    mem,
+};
+use phenotype_internal::Phenotype;
+
+/// How an enum is laid out by [`Phenotype`](crate::Phenotype), compared to how
+/// the compiler lays it out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumLayout {
+    /// The name of the type, from [`core::any::type_name`].
+    pub name: &'static str,
+    /// [`Phenotype::NUM_VARIANTS`](crate::Phenotype::NUM_VARIANTS)
+    pub num_variants: usize,
+    /// [`Phenotype::BITS`](crate::Phenotype::BITS)
+    pub bits: usize,
+    /// The name and payload size in bytes of each variant, in tag order.
+    pub payloads: Vec<(&'static str, usize)>,
+    /// The size in bytes of [`Phenotype::Value`](crate::Phenotype::Value).
+    pub union_size: usize,
+    /// The size in bytes of the enum itself.
+    pub size_of: usize,
+    /// [`Phenotype::PEAPOD_SIZE`](crate::Phenotype::PEAPOD_SIZE): the tag,
+    /// rounded up to whole bytes, plus `union_size`.
+    pub peapod_size: usize,
+}
+
+impl EnumLayout {
+    /// Returns the layout of `T`.
+    pub fn of<T: Phenotype>() -> Self {
+        let union_size = mem::size_of::<T::Value>();
+        Self {
+            name: any::type_name::<T>(),
+            num_variants: T::NUM_VARIANTS,
+            bits: T::BITS,
+            payloads: T::VARIANTS
+                .iter()
+                .map(|variant| (variant.name, variant.payload_size))
+                .collect(),
+            union_size,
+            size_of: mem::size_of::<T>(),
+            // Generic enums don't know their size until they're instantiated,
+            // but by now they have been
+            peapod_size: T::PEAPOD_SIZE.unwrap_or(T::BITS.div_ceil(8) + union_size),
+        }
+    }
+
+    /// Returns the bytes `n` elements take up in a `Vec`.
+    pub fn vec_bytes(&self, n: usize) -> usize {
+        n * self.size_of
+    }
+
+    /// Returns the bytes `n` elements take up in a [`Peapod`](crate::Peapod)
+    /// with the default tag layout, which packs tags into `usize`s.
+    pub fn peapod_bytes(&self, n: usize) -> usize {
+        let word = usize::BITS as usize;
+        (n * self.bits).div_ceil(word) * mem::size_of::<usize>() + n * self.union_size
+    }
+
+    /// Returns how many fewer bytes `n` elements take up in a
+    /// [`Peapod`](crate::Peapod) than in a `Vec`. Negative if the `Peapod`
+    /// is bigger.
+    pub fn savings(&self, n: usize) -> isize {
+        self.vec_bytes(n) as isize - self.peapod_bytes(n) as isize
+    }
+}
+
+/// A report on how a set of enums are laid out, and how much space storing
+/// them in a [`Peapod`](crate::Peapod) saves.
+///
+/// [`Display`] prints the report as a table, and [`LayoutReport::to_json`] as
+/// JSON, so it can be pasted into a PR or checked by CI.
+///
+/// ```rust
+/// use peapod::{LayoutReport, Phenotype};
+///
+/// #[derive(Phenotype)]
+/// enum Shape {
+///     Circle(f32),
+///     Rect { w: f32, h: f32 },
+///     Empty,
+/// }
+///
+/// let mut report = LayoutReport::new(1000);
+/// report.add::<Shape>();
+///
+/// let shape = &report.layouts()[0];
+/// assert_eq!(shape.bits, 2);
+/// assert_eq!(shape.payloads[1], ("Rect", 8));
+/// assert_eq!(shape.vec_bytes(1000), 12000);
+/// assert_eq!(shape.peapod_bytes(1000), 8256);
+/// println!("{report}");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutReport {
+    elements: usize,
+    layouts: Vec<EnumLayout>,
+}
+
+impl LayoutReport {
+    /// Creates an empty report, which projects savings for `elements` elements.
+    pub fn new(elements: usize) -> Self {
+        Self {
+            elements,
+            layouts: Vec::new(),
+        }
+    }
+
+    /// Adds `T` to the report.
+    pub fn add<T: Phenotype>(&mut self) -> &mut Self {
+        self.layouts.push(EnumLayout::of::<T>());
+        self
+    }
+
+    /// Returns the number of elements savings are projected for.
+    pub fn elements(&self) -> usize {
+        self.elements
+    }
+
+    /// Returns the layouts of the enums in the report, in the order they were
+    /// added.
+    pub fn layouts(&self) -> &[EnumLayout] {
+        &self.layouts
+    }
+
+    /// Returns the report as a JSON array with an object for each enum.
+    pub fn to_json(&self) -> String {
+        // Type and variant names never hold quotes or backslashes, so there's
+        // nothing to escape
+        let mut json = String::from("[");
+        for (i, layout) in self.layouts.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let n = self.elements;
+            let _ = write!(
+                json,
+                "{{\"name\":\"{}\",\"num_variants\":{},\"bits\":{},\"payloads\":{{",
+                layout.name, layout.num_variants, layout.bits
+            );
+            for (j, (variant, size)) in layout.payloads.iter().enumerate() {
+                if j > 0 {
+                    json.push(',');
+                }
+                let _ = write!(json, "\"{variant}\":{size}");
+            }
+            let _ = write!(
+                json,
+                "}},\"union_size\":{},\"size_of\":{},\"peapod_size\":{},\
+                 \"elements\":{n},\"vec_bytes\":{},\"peapod_bytes\":{},\"savings\":{}}}",
+                layout.union_size,
+                layout.size_of,
+                layout.peapod_size,
+                layout.vec_bytes(n),
+                layout.peapod_bytes(n),
+                layout.savings(n),
+            );
+        }
+        json.push(']');
+        json
+    }
+}
+
+impl Display for LayoutReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let n = self.elements;
+        let header = [
+            "enum",
+            "variants",
+            "bits",
+            "union",
+            "size_of",
+            "peapod",
+            "Vec bytes",
+            "Peapod bytes",
+            "savings",
+        ];
+        let rows = self
+            .layouts
+            .iter()
+            .map(|layout| {
+                let vec = layout.vec_bytes(n);
+                let savings = match vec {
+                    0 => String::from("-"),
+                    _ => alloc::format!(
+                        "{} ({:.1}%)",
+                        layout.savings(n),
+                        layout.savings(n) as f64 * 100.0 / vec as f64
+                    ),
+                };
+                [
+                    String::from(layout.name),
+                    layout.num_variants.to_string(),
+                    layout.bits.to_string(),
+                    layout.union_size.to_string(),
+                    layout.size_of.to_string(),
+                    layout.peapod_size.to_string(),
+                    vec.to_string(),
+                    layout.peapod_bytes(n).to_string(),
+                    savings,
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let mut widths = header.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        writeln!(f, "Sizes in bytes, projected for {n} elements")?;
+        // Names are left-aligned, numbers right-aligned
+        let line = |f: &mut fmt::Formatter<'_>, cells: &[&str]| {
+            for (col, (cell, width)) in cells.iter().zip(widths).enumerate() {
+                match col {
+                    0 => write!(f, "{cell:<width$}")?,
+                    _ => write!(f, "  {cell:>width$}")?,
+                }
+            }
+            writeln!(f)
+        };
+        line(f, &header)?;
+        for (layout, row) in self.layouts.iter().zip(&rows) {
+            line(f, &row.each_ref().map(String::as_str))?;
+            for (variant, size) in &layout.payloads {
+                writeln!(f, "  {variant}: {size}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::{Phenotype, VariantInfo};
+
+    #[allow(dead_code)]
+    #[derive(Phenotype)]
+    enum Op {
+        Push(u64),
+        Jump { to: u32, cond: bool },
+        Halt,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Phenotype)]
+    enum Wrapper<T> {
+        Some(T),
+        None,
+    }
+
+    #[test]
+    fn payload_sizes() {
+        assert_eq!(Op::PAYLOAD_SIZES, &[8, 5, 0]);
+        assert_eq!(<Wrapper<u16>>::PAYLOAD_SIZES, &[2, 0]);
+        assert_eq!(<Wrapper<[u8; 3]>>::PAYLOAD_SIZES, &[3, 0]);
+    }
+
+    #[test]
+    fn variants() {
+        assert_eq!(
+            Op::VARIANTS,
+            &[
+                VariantInfo {
+                    name: "Push",
+                    tag: 0,
+                    field_names: &["0"],
+                    field_types: &["u64"],
+                    payload_size: 8,
+                    inline_bits: 0,
+                    align: 8,
+                },
+                VariantInfo {
+                    name: "Jump",
+                    tag: 1,
+                    field_names: &["to", "cond"],
+                    field_types: &["u32", "bool"],
+                    payload_size: 5,
+                    inline_bits: 0,
+                    align: 4,
+                },
+                VariantInfo {
+                    name: "Halt",
+                    tag: 2,
+                    field_names: &[],
+                    field_types: &[],
+                    payload_size: 0,
+                    inline_bits: 0,
+                    align: 1,
+                },
+            ]
+        );
+
+        let some = <Wrapper<u16>>::VARIANTS[0];
+        assert_eq!(some.field_types, &["T"]);
+        assert_eq!((some.payload_size, some.align), (2, 2));
+    }
+
+    #[allow(dead_code)]
+    #[derive(Phenotype)]
+    enum Typed<'a, T> {
+        Nested(alloc::vec::Vec<Option<T>>, [u8; 3]),
+        Ref { r: &'a mut T, p: *const (u8, u16) },
+        Path(core::num::NonZeroU8),
+        Call(fn(u8) -> u8, &'a dyn Fn(T)),
+    }
+
+    #[test]
+    fn variant_type_names() {
+        let types = <Typed<'_, u8>>::VARIANTS
+            .iter()
+            .flat_map(|variant| variant.field_types)
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            [
+                "alloc::vec::Vec<Option<T>>",
+                "[u8; 3]",
+                "&'a mut T",
+                "*const (u8, u16)",
+                "core::num::NonZeroU8",
+                "fn(u8) -> u8",
+                "&'a dyn Fn(T)",
+            ]
+        );
+    }
+
+    #[test]
+    fn layout() {
+        let op = EnumLayout::of::<Op>();
+        assert_eq!(op.num_variants, 3);
+        assert_eq!(op.bits, 2);
+        assert_eq!(op.payloads, [("Push", 8), ("Jump", 5), ("Halt", 0)]);
+        assert_eq!(op.union_size, 8);
+        assert_eq!(op.size_of, 16);
+        assert_eq!(op.peapod_size, 9);
+
+        // 64 2-bit tags fit in two words
+        assert_eq!(op.vec_bytes(64), 1024);
+        assert_eq!(op.peapod_bytes(64), 64 * 8 + 16);
+        assert_eq!(op.savings(64), 1024 - 528);
+
+        let wrapper = EnumLayout::of::<Wrapper<u8>>();
+        assert_eq!(wrapper.peapod_size, 2);
+        // A lone tag still takes up a whole word
+        assert!(wrapper.savings(1) < 0);
+    }
+
+    #[test]
+    fn table() {
+        let mut report = LayoutReport::new(64);
+        report.add::<Op>().add::<Wrapper<u8>>();
+        let table = report.to_string();
+        let mut lines = table.lines();
+        assert_eq!(
+            lines.next(),
+            Some("Sizes in bytes, projected for 64 elements")
+        );
+        assert!(lines.next().unwrap().starts_with("enum"));
+        assert!(lines.next().unwrap().ends_with("496 (48.4%)"));
+        assert_eq!(lines.next(), Some("  Push: 8"));
+    }
+
+    #[test]
+    fn json() {
+        let mut report = LayoutReport::new(10);
+        report.add::<Wrapper<u8>>();
+        assert_eq!(
+            report.to_json(),
+            alloc::format!(
+                "[{{\"name\":\"{}\",\"num_variants\":2,\"bits\":1,\
+                 \"payloads\":{{\"Some\":1,\"None\":0}},\
+                 \"union_size\":1,\"size_of\":2,\"peapod_size\":2,\"elements\":10,\
+                 \"vec_bytes\":20,\"peapod_bytes\":18,\"savings\":2}}]",
+                any::type_name::<Wrapper<u8>>()
+            )
+        );
+        assert_eq!(LayoutReport::new(10).to_json(), "[]");
+    }
+}
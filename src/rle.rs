@@ -0,0 +1,639 @@
+extern crate alloc;
+use crate::{sparse::unit_value, PeaRef, Peapod, TagOrder};
+use alloc::vec::{self, Vec};
+use bitvec::prelude::*;
+use core::{
+    fmt::{self, Debug},
+    marker::PhantomData,
+    mem::{self, ManuallyDrop},
+    ops::Range,
+    ptr,
+};
+use phenotype_internal::{Frozen, Phenotype};
+
+/// A `Peapod`-like collection for `enum`s that come in long runs of the same
+/// variant, like a time-series of statuses.
+///
+/// Instead of storing a tag per element, the tags are stored as runs of
+/// `(tag, run length)`, along with running totals that allow finding the run an
+/// element is in with a binary search. Only elements whose variant carries data
+/// (see [`Phenotype::HAS_DATA`]) store a `T::Value`, so a run of unit variants
+/// costs the same no matter how long it is.
+pub struct RlePeapod<T>
+where
+    T: Phenotype,
+{
+    // The tag of each run
+    tags: BitVec,
+    // The index one past the last element of each run, i.e. the running total
+    // of the run lengths
+    ends: Vec<usize>,
+    // The number of payloads before each run
+    payload_starts: Vec<usize>,
+    data: Vec<T::Value>,
+    // See Peapod
+    _marker: PhantomData<*const T>,
+}
+
+// # Safety
+// A `RlePeapod` owns its elements like a `Vec<T>` does
+unsafe impl<T> Send for RlePeapod<T> where T: Phenotype + Send {}
+unsafe impl<T> Sync for RlePeapod<T> where T: Phenotype + Sync {}
+
+impl<T> RlePeapod<T>
+where
+    T: Phenotype,
+{
+    /// Create a new `RlePeapod` with 0 length. This does not allocate.
+    pub fn new() -> Self {
+        Self {
+            tags: BitVec::new(),
+            ends: Vec::new(),
+            payload_starts: Vec::new(),
+            data: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    // **Note**: run must be in range
+    fn run_tag(&self, run: usize) -> usize {
+        Lsb0::load_tag(&self.tags[run * T::BITS..(run + 1) * T::BITS])
+    }
+
+    // **Note**: run must be in range
+    fn run_start(&self, run: usize) -> usize {
+        match run {
+            0 => 0,
+            _ => self.ends[run - 1],
+        }
+    }
+
+    // The run element `index` is in
+    // **Note**: index must be in range
+    fn run_of(&self, index: usize) -> usize {
+        self.ends.partition_point(|&end| end <= index)
+    }
+
+    // # Safety
+    // index must be in range and in the run
+    unsafe fn view(&self, run: usize, index: usize) -> PeaRef<'_, T> {
+        let tag = self.run_tag(run);
+        if T::HAS_DATA[T::variant(tag)] {
+            let payload = self.payload_starts[run] + index - self.run_start(run);
+            PeaRef::new(tag, &self.data[payload])
+        } else {
+            PeaRef::from_value(tag, unit_value::<T>())
+        }
+    }
+
+    /// Append a new element to the end of the collection.
+    ///
+    /// ## Panics
+    /// Panics in the same cases as [`Peapod::push`]. If it panics, the
+    /// collection is left unchanged.
+    pub fn push(&mut self, t: T) {
+        // Make sure none of the pushes below can panic, so the runs and data
+        // can never get out of step.
+        // See https://github.com/fprasx/peapod/issues/2
+        self.tags.reserve(T::BITS);
+        self.ends.reserve(1);
+        self.payload_starts.reserve(1);
+        self.data.reserve(1);
+
+        let (tag, data) = t.cleave();
+        let len = self.len();
+
+        match self.ends.len() {
+            // Extend the last run
+            runs if runs > 0 && self.run_tag(runs - 1) == tag => self.ends[runs - 1] += 1,
+            // Start a new one
+            runs => {
+                self.tags.resize((runs + 1) * T::BITS, false);
+                Lsb0::store_tag(&mut self.tags[runs * T::BITS..(runs + 1) * T::BITS], tag);
+                self.ends.push(len + 1);
+                self.payload_starts.push(self.data.len());
+            }
+        }
+
+        // Unit variants don't hold anything in `data`, so there is nothing to drop
+        if T::HAS_DATA[T::variant(tag)] {
+            self.data.push(data);
+        }
+    }
+
+    /// Remove an element from the end of the collection.
+    /// Returns `None` if the collection is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        let run = self.ends.len().checked_sub(1)?;
+        let tag = self.run_tag(run);
+
+        self.ends[run] -= 1;
+        // Get rid of the run if that was its last element
+        if self.ends[run] == self.run_start(run) {
+            self.tags.truncate(run * T::BITS);
+            self.ends.pop();
+            self.payload_starts.pop();
+        }
+
+        let data = if T::HAS_DATA[T::variant(tag)] {
+            // Every element with data has a matching value
+            self.data.pop().unwrap()
+        } else {
+            // # Safety
+            // The element is a unit variant
+            unsafe { unit_value::<T>() }
+        };
+
+        // # Safety
+        // The tag matches the data
+        unsafe { Some(Phenotype::reknit(tag, data)) }
+    }
+
+    /// Returns a reference to the element at `index`, or `None` if it is out
+    /// of bounds.
+    pub fn get(&self, index: usize) -> Option<PeaRef<'_, T>> {
+        if index >= self.len() {
+            return None;
+        }
+        // # Safety
+        // The element is in the run we found
+        unsafe { Some(self.view(self.run_of(index), index)) }
+    }
+
+    /// Returns the tag of the element at `index`, or `None` if it is out
+    /// of bounds. Like [`Peapod::tag`], this leaves out any fields packed into
+    /// the tag.
+    pub fn tag(&self, index: usize) -> Option<usize> {
+        (index < self.len()).then(|| T::variant(self.run_tag(self.run_of(index))))
+    }
+
+    /// Returns an iterator over references to the elements of the collection.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            pod: self,
+            index: 0,
+            run: 0,
+        }
+    }
+
+    /// Returns an iterator over the runs in the collection, as pairs of the tag
+    /// of the run and the range of indices it covers.
+    ///
+    /// Fields packed into the tag are part of the run, so `Level::Custom(1)`
+    /// and `Level::Custom(2)` are in different runs with the same tag.
+    pub fn runs(&self) -> impl Iterator<Item = (usize, Range<usize>)> + '_ {
+        (0..self.ends.len()).map(|run| {
+            (
+                T::variant(self.run_tag(run)),
+                self.run_start(run)..self.ends[run],
+            )
+        })
+    }
+
+    /// Returns the number of runs in the collection.
+    pub fn run_count(&self) -> usize {
+        self.ends.len()
+    }
+
+    /// Returns the number of elements in the collection.
+    pub fn len(&self) -> usize {
+        self.ends.last().copied().unwrap_or(0)
+    }
+
+    /// Returns whether the collection is empty (it contains no elements).
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// Removes all elements from the collection.
+    /// **Note**: this does not affect its allocated capacity.
+    pub fn clear(&mut self) {
+        while self.pop().is_some() {}
+    }
+
+    /// Returns how many times smaller this collection is than a [`Peapod`] holding
+    /// the same elements. Only the space the elements take up is counted, not
+    /// unused capacity. A result under 1.0 means a `Peapod` would be smaller.
+    pub fn compression_ratio(&self) -> f64 {
+        let value_bits = mem::size_of::<T::Value>() * 8;
+        let peapod_bits = self.len() * (T::BITS + value_bits);
+        // Each run stores a tag and two running totals
+        let rle_bits = self.run_count() * (T::BITS + 2 * mem::size_of::<usize>() * 8)
+            + self.data.len() * value_bits;
+        if rle_bits == 0 {
+            1.0
+        } else {
+            peapod_bits as f64 / rle_bits as f64
+        }
+    }
+
+    fn cleave(self) -> (BitVec, Vec<usize>, Vec<T::Value>) {
+        let levitating = ManuallyDrop::new(self);
+        unsafe {
+            // # Safety
+            // We are reading from a reference,
+            // we have wrapped self in ManuallyDrop to prevent a double-free.
+            // The payload starts are dropped here as they aren't needed anymore.
+            drop(ptr::read(&levitating.payload_starts));
+            (
+                ptr::read(&levitating.tags),
+                ptr::read(&levitating.ends),
+                ptr::read(&levitating.data),
+            )
+        }
+    }
+}
+
+impl<T> Drop for RlePeapod<T>
+where
+    T: Phenotype,
+{
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+impl<T> Default for RlePeapod<T>
+where
+    T: Phenotype,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Debug for RlePeapod<T>
+where
+    T: Phenotype + Debug + Frozen,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> Extend<T> for RlePeapod<T>
+where
+    T: Phenotype,
+{
+    fn extend<A: IntoIterator<Item = T>>(&mut self, iter: A) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for RlePeapod<T>
+where
+    T: Phenotype,
+{
+    fn from_iter<A: IntoIterator<Item = T>>(iter: A) -> Self {
+        let mut pp = Self::new();
+        pp.extend(iter);
+        pp
+    }
+}
+
+impl<T> From<Peapod<T>> for RlePeapod<T>
+where
+    T: Phenotype,
+{
+    fn from(pp: Peapod<T>) -> Self {
+        pp.into_iter().collect()
+    }
+}
+
+impl<T> From<RlePeapod<T>> for Peapod<T>
+where
+    T: Phenotype,
+{
+    fn from(pp: RlePeapod<T>) -> Self {
+        let mut new = Peapod::with_capacity(pp.len());
+        new.extend(pp);
+        new
+    }
+}
+
+/// An iterator over references to the elements of a [`RlePeapod`].
+pub struct Iter<'a, T>
+where
+    T: Phenotype,
+{
+    pod: &'a RlePeapod<T>,
+    index: usize,
+    // The run `index` is in
+    run: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T>
+where
+    T: Phenotype,
+{
+    type Item = PeaRef<'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Are we done iterating?
+        if self.index == self.pod.len() {
+            return None;
+        }
+
+        if self.pod.ends[self.run] == self.index {
+            self.run += 1;
+        }
+        // # Safety
+        // We just made sure the element is in the run
+        let elem = unsafe { self.pod.view(self.run, self.index) };
+        self.index += 1;
+        Some(elem)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.pod.len() - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> where T: Phenotype {}
+
+impl<'a, T> IntoIterator for &'a RlePeapod<T>
+where
+    T: Phenotype,
+{
+    type Item = PeaRef<'a, T>;
+
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for RlePeapod<T>
+where
+    T: Phenotype,
+{
+    type Item = T;
+
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let (tags, ends, data) = self.cleave();
+        IntoIter {
+            front: 0,
+            front_run: 0,
+            back: ends.last().copied().unwrap_or(0),
+            back_run: ends.len().saturating_sub(1),
+            tags,
+            ends,
+            data: data.into_iter(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// An owning iterator over the elements of a [`RlePeapod`].
+pub struct IntoIter<T>
+where
+    T: Phenotype,
+{
+    tags: BitVec,
+    ends: Vec<usize>,
+    data: vec::IntoIter<T::Value>,
+    // The next index to yield from the front, and the run it's in
+    front: usize,
+    front_run: usize,
+    // One past the next index to yield from the back, and the run that index is in
+    back: usize,
+    back_run: usize,
+    _marker: PhantomData<*const T>,
+}
+
+// # Safety
+// An `IntoIter` owns the elements that are left, like `RlePeapod`
+unsafe impl<T> Send for IntoIter<T> where T: Phenotype + Send {}
+unsafe impl<T> Sync for IntoIter<T> where T: Phenotype + Sync {}
+
+impl<T> IntoIter<T>
+where
+    T: Phenotype,
+{
+    // # Safety
+    // All the payloads between the element and the end it is taken from must have
+    // already been yielded
+    unsafe fn take(&mut self, run: usize, from_back: bool) -> T {
+        let tag = Lsb0::load_tag(&self.tags[run * T::BITS..(run + 1) * T::BITS]);
+        let data = if T::HAS_DATA[T::variant(tag)] {
+            let next = if from_back {
+                self.data.next_back()
+            } else {
+                self.data.next()
+            };
+            // Every element with data has a matching value
+            next.unwrap()
+        } else {
+            unit_value::<T>()
+        };
+        T::reknit(tag, data)
+    }
+}
+
+impl<T> Iterator for IntoIter<T>
+where
+    T: Phenotype,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Are we done iterating?
+        if self.front == self.back {
+            return None;
+        }
+
+        if self.ends[self.front_run] == self.front {
+            self.front_run += 1;
+        }
+        // # Safety
+        // Elements are taken from the front in order
+        let elem = unsafe { self.take(self.front_run, false) };
+        self.front += 1;
+        Some(elem)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T>
+where
+    T: Phenotype,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        // Are we done iterating?
+        if self.front == self.back {
+            return None;
+        }
+
+        self.back -= 1;
+        if self.back_run > 0 && self.ends[self.back_run - 1] > self.back {
+            self.back_run -= 1;
+        }
+        // # Safety
+        // Elements are taken from the back in order
+        unsafe { Some(self.take(self.back_run, true)) }
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> where T: Phenotype {}
+
+impl<T> Drop for IntoIter<T>
+where
+    T: Phenotype,
+{
+    fn drop(&mut self) {
+        for _ in self {}
+        // The remaining values in self.data are ManuallyDrop'd union fields,
+        // so they have all been dropped by the loop above
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{string::String, vec};
+    use phenotype_macro::Phenotype;
+
+    #[derive(Phenotype, PartialEq, Debug)]
+    enum Status {
+        Up,
+        Down,
+        Degraded(String),
+    }
+
+    fn statuses() -> Vec<Status> {
+        let mut statuses = Vec::new();
+        for _ in 0..1000 {
+            statuses.push(Status::Up);
+        }
+        statuses.push(Status::Degraded(String::from("slow")));
+        statuses.push(Status::Degraded(String::from("slower")));
+        for _ in 0..500 {
+            statuses.push(Status::Down);
+        }
+        statuses.push(Status::Up);
+        statuses
+    }
+
+    #[test]
+    fn runs_are_merged() {
+        let pp: RlePeapod<_> = statuses().into_iter().collect();
+        assert_eq!(pp.len(), 1503);
+        assert_eq!(pp.run_count(), 4);
+        assert_eq!(
+            pp.runs().collect::<Vec<_>>(),
+            vec![
+                (0, 0..1000),
+                (2, 1000..1002),
+                (1, 1002..1502),
+                (0, 1502..1503)
+            ]
+        );
+    }
+
+    #[test]
+    fn get_and_iter() {
+        let pp: RlePeapod<_> = statuses().into_iter().collect();
+        for (i, status) in statuses().iter().enumerate() {
+            assert_eq!(*pp.get(i).unwrap(), *status);
+        }
+        assert!(pp.get(1503).is_none());
+        assert_eq!(pp.tag(1001), Some(2));
+        assert!(pp.iter().zip(statuses()).all(|(a, b)| *a == b));
+    }
+
+    #[test]
+    fn pop_across_runs() {
+        let mut pp: RlePeapod<_> = statuses().into_iter().collect();
+        let mut expected = statuses();
+        while let Some(status) = pp.pop() {
+            assert_eq!(Some(status), expected.pop());
+        }
+        assert!(pp.is_empty());
+        assert_eq!(pp.run_count(), 0);
+    }
+
+    #[test]
+    fn into_iter_both_ends() {
+        let pp: RlePeapod<_> = statuses().into_iter().collect();
+        let mut iter = pp.into_iter();
+        assert_eq!(iter.next_back(), Some(Status::Up));
+        assert_eq!(iter.next_back(), Some(Status::Down));
+        assert_eq!(iter.next(), Some(Status::Up));
+        assert_eq!(iter.len(), 1500);
+        let rest: Vec<_> = iter.rev().collect();
+        assert_eq!(rest[499], Status::Degraded(String::from("slower")));
+        assert_eq!(rest[500], Status::Degraded(String::from("slow")));
+    }
+
+    #[test]
+    fn compresses_long_runs() {
+        let pp: RlePeapod<_> = statuses().into_iter().collect();
+        assert!(pp.compression_ratio() > 100.0);
+
+        let alternating: RlePeapod<_> = (0..100)
+            .map(|i| match i % 2 {
+                0 => Status::Up,
+                _ => Status::Degraded(String::new()),
+            })
+            .collect();
+        assert!(alternating.compression_ratio() < 1.0);
+    }
+
+    #[test]
+    fn peapod_round_trip() {
+        let rle = RlePeapod::from(Peapod::from(statuses()));
+        assert_eq!(Vec::from(Peapod::from(rle)), statuses());
+    }
+
+    #[test]
+    fn inline_fields_split_runs() {
+        #[derive(Phenotype, PartialEq, Debug)]
+        enum Retry {
+            Never,
+            After(#[phenotype(bits = 4)] u8),
+        }
+
+        let pp: RlePeapod<_> = [
+            Retry::After(1),
+            Retry::After(1),
+            Retry::After(2),
+            Retry::Never,
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            pp.runs().collect::<Vec<_>>(),
+            vec![(1, 0..2), (1, 2..3), (0, 3..4)]
+        );
+        assert_eq!(*pp.get(2).unwrap(), Retry::After(2));
+    }
+
+    #[test]
+    fn over_aligned_payloads() {
+        use crate::test_util::wide;
+
+        wide!();
+
+        let wide = |i: u64| match i / 4 {
+            1 => Wide::lanes(i),
+            _ => Wide::Empty,
+        };
+        let mut pp: RlePeapod<Wide<u128>> = (0..10).map(wide).collect();
+        assert_eq!(pp.run_count(), 3);
+        assert_eq!(*pp.get(5).unwrap(), wide(5));
+        assert_eq!(pp.pop(), Some(wide(9)));
+        let mut iter = pp.into_iter();
+        assert_eq!(iter.next(), Some(wide(0)));
+        assert_eq!(iter.next_back(), Some(wide(8)));
+    }
+}
@@ -0,0 +1,466 @@
+extern crate alloc;
+use crate::{concurrent::Chunk, PeaRef};
+use alloc::boxed::Box;
+use core::{
+    fmt::{self, Debug},
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+use phenotype_internal::{Frozen, Phenotype};
+
+const WORD_BITS: usize = usize::BITS as usize;
+
+// The number of elements in each segment
+const SEGMENT_LEN: usize = 1024;
+const READY_WORDS: usize = SEGMENT_LEN / WORD_BITS;
+// Directory block k holds 2^k segments, which is plenty to cover every index
+const DIRECTORY_BLOCKS: usize = WORD_BITS;
+
+/// An append-only collection of `enum`s that many threads can push to at once,
+/// while others read from it, all without locks.
+///
+/// Elements are stored `cleave`d in fixed-size segments, each with its own
+/// block of tags and block of data, that never move once they're allocated.
+/// A push reserves a slot with a single atomic increment and writes the element
+/// there, so producers only contend on that counter.
+///
+/// Producers can finish in a different order than they reserved their slots,
+/// so readers only see the *published* prefix: the longest run of elements from
+/// the start that have all been written. An element shows up as soon as every
+/// element before it has been written too.
+///
+/// If there's only ever one producer, [`ConcurrentPeapod`](crate::ConcurrentPeapod)
+/// allocates less for small collections.
+pub struct SegmentedPeapod<T>
+where
+    T: Phenotype,
+{
+    // Directory block k holds the pointers to segments 2^k - 1 to 2^(k + 1) - 2.
+    // Each block is a boxed slice, stored as a pointer to its first element, and
+    // is null until a segment in it is needed
+    directory: [AtomicPtr<AtomicPtr<Segment<T>>>; DIRECTORY_BLOCKS],
+    // The number of slots handed out to producers
+    reserved: AtomicUsize,
+    // The length of the prefix whose elements have all been written
+    published: AtomicUsize,
+}
+
+// # Safety
+// Same as ConcurrentPeapod: any thread with a shared reference can push elements
+// in and read them out as `PeaRef`s, which only exist for `Frozen` elements
+unsafe impl<T> Send for SegmentedPeapod<T> where T: Phenotype + Send {}
+unsafe impl<T> Sync for SegmentedPeapod<T> where T: Phenotype + Send + Sync {}
+
+struct Segment<T>
+where
+    T: Phenotype,
+{
+    chunk: Chunk<T>,
+    // One bit per slot, set once the element in it has been written
+    ready: [AtomicUsize; READY_WORDS],
+}
+
+impl<T> Segment<T>
+where
+    T: Phenotype,
+{
+    fn new() -> Self {
+        Self {
+            chunk: Chunk::new(SEGMENT_LEN),
+            ready: [const { AtomicUsize::new(0) }; READY_WORDS],
+        }
+    }
+
+    // Publishing relies on each producer seeing either the other's ready bit or
+    // the other's published length, so these are SeqCst, see publish
+    fn is_ready(&self, offset: usize) -> bool {
+        self.ready[offset / WORD_BITS].load(Ordering::SeqCst) >> (offset % WORD_BITS) & 1 == 1
+    }
+
+    fn set_ready(&self, offset: usize) {
+        self.ready[offset / WORD_BITS].fetch_or(1 << (offset % WORD_BITS), Ordering::SeqCst);
+    }
+}
+
+// Finds the directory block a segment is in and its position in that block
+fn locate(segment: usize) -> (usize, usize) {
+    // Shifting the index makes the block boundaries land on powers of two
+    let shifted = segment + 1;
+    let block = (usize::BITS - 1 - shifted.leading_zeros()) as usize;
+    (block, shifted - (1 << block))
+}
+
+// Loads the pointer in `slot`, or fills it in with a new one from `make` if it's
+// null. If another thread fills it in first, ours is passed to `free`
+fn get_or_init<U>(
+    slot: &AtomicPtr<U>,
+    make: impl FnOnce() -> *mut U,
+    free: impl FnOnce(*mut U),
+) -> *mut U {
+    let current = slot.load(Ordering::Acquire);
+    if !current.is_null() {
+        return current;
+    }
+    let new = make();
+    match slot.compare_exchange(ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => new,
+        Err(winner) => {
+            free(new);
+            winner
+        }
+    }
+}
+
+impl<T> SegmentedPeapod<T>
+where
+    T: Phenotype,
+{
+    /// Create a new, empty `SegmentedPeapod`. This does not allocate.
+    pub const fn new() -> Self {
+        Self {
+            directory: [const { AtomicPtr::new(ptr::null_mut()) }; DIRECTORY_BLOCKS],
+            reserved: AtomicUsize::new(0),
+            published: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of published elements, see the [type docs](SegmentedPeapod).
+    /// Elements can be pushed concurrently, so this can be out of date as soon
+    /// as it returns, but it never goes down.
+    pub fn len(&self) -> usize {
+        self.published.load(Ordering::Acquire)
+    }
+
+    /// Returns whether no elements have been published yet.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Pushes an element to the end of the collection, and returns its index.
+    ///
+    /// The element is published once every element before it has been written,
+    /// which may be after this returns.
+    pub fn push(&self, elem: T) -> usize {
+        // Cleave before reserving a slot: if it panics, say because a field
+        // doesn't fit in its bits, a reserved slot would never be marked ready,
+        // and nothing after it would ever be published
+        let (tag, value) = elem.cleave();
+        let index = self.reserved.fetch_add(1, Ordering::Relaxed);
+        let segment = self.segment_or_alloc(index / SEGMENT_LEN);
+        let offset = index % SEGMENT_LEN;
+        // # Safety
+        // The slot was reserved for us alone, and nobody reads it until it is
+        // marked ready and published
+        unsafe { segment.chunk.write(offset, tag, value) };
+        segment.set_ready(offset);
+        self.publish();
+        index
+    }
+
+    /// Returns a reference to the element at `index`, or `None` if it hasn't
+    /// been published yet. See [`PeaRef`] for how the reference works.
+    pub fn get(&self, index: usize) -> Option<PeaRef<'_, T>>
+    where
+        T: Frozen,
+    {
+        if index >= self.len() {
+            return None;
+        }
+        // # Safety
+        // The element has been published, so it was written before the length we
+        // loaded, and never changes again
+        unsafe {
+            Some(
+                self.segment(index / SEGMENT_LEN)?
+                    .chunk
+                    .get(index % SEGMENT_LEN),
+            )
+        }
+    }
+
+    /// Returns the tag of the element at `index`, or `None` if it hasn't been
+    /// published yet.
+    pub fn tag(&self, index: usize) -> Option<usize> {
+        if index >= self.len() {
+            return None;
+        }
+        Some(T::variant(
+            self.segment(index / SEGMENT_LEN)?
+                .chunk
+                .tag(index % SEGMENT_LEN),
+        ))
+    }
+
+    /// Returns an iterator over the elements that had been published when this
+    /// was called. Elements published while iterating aren't included.
+    pub fn iter(&self) -> impl Iterator<Item = PeaRef<'_, T>> + '_
+    where
+        T: Frozen,
+    {
+        (0..self.len()).map(|i| self.get(i).expect("elements are never removed"))
+    }
+
+    // Moves the published length past every element that has been written.
+    // Every producer does this after marking its element ready, so whoever
+    // writes the element the prefix is stuck on carries it past everything
+    // written after it. Since the ready bits and the length are SeqCst, a
+    // producer that stops early because our element wasn't ready yet is always
+    // seen by us, and we carry on from where it stopped
+    fn publish(&self) {
+        let mut published = self.published.load(Ordering::SeqCst);
+        while self
+            .segment(published / SEGMENT_LEN)
+            .is_some_and(|segment| segment.is_ready(published % SEGMENT_LEN))
+        {
+            match self.published.compare_exchange_weak(
+                published,
+                published + 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => published += 1,
+                Err(current) => published = current,
+            }
+        }
+    }
+
+    fn segment(&self, segment: usize) -> Option<&Segment<T>> {
+        let (block, position) = locate(segment);
+        let block = self.directory[block].load(Ordering::Acquire);
+        if block.is_null() {
+            return None;
+        }
+        // # Safety
+        // Blocks and segments are only freed on drop, and the block is as long
+        // as its index says
+        unsafe {
+            let segment = (*block.add(position)).load(Ordering::Acquire);
+            segment.as_ref()
+        }
+    }
+
+    fn segment_or_alloc(&self, segment: usize) -> &Segment<T> {
+        let (block, position) = locate(segment);
+        let len = 1 << block;
+        let block = get_or_init(
+            &self.directory[block],
+            || {
+                let block: Box<[AtomicPtr<Segment<T>>]> =
+                    (0..len).map(|_| AtomicPtr::new(ptr::null_mut())).collect();
+                Box::into_raw(block).cast()
+            },
+            // # Safety
+            // The block came from Box::into_raw above, with this length
+            |block| drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(block, len)) }),
+        );
+        // # Safety
+        // See segment. A few producers may allocate the same segment at once,
+        // but only one of the segments is kept, and the others were never used
+        unsafe {
+            let segment = get_or_init(
+                &*block.add(position),
+                || Box::into_raw(Box::new(Segment::new())),
+                |segment| drop(Box::from_raw(segment)),
+            );
+            &*segment
+        }
+    }
+}
+
+impl<T> Default for SegmentedPeapod<T>
+where
+    T: Phenotype,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Debug for SegmentedPeapod<T>
+where
+    T: Phenotype + Debug + Frozen,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> Extend<T> for SegmentedPeapod<T>
+where
+    T: Phenotype,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for SegmentedPeapod<T>
+where
+    T: Phenotype,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut pp = Self::new();
+        pp.extend(iter);
+        pp
+    }
+}
+
+impl<T> Drop for SegmentedPeapod<T>
+where
+    T: Phenotype,
+{
+    fn drop(&mut self) {
+        for (block, segments) in self.directory.iter_mut().enumerate() {
+            let segments = *segments.get_mut();
+            if segments.is_null() {
+                continue;
+            }
+            // # Safety
+            // The block came from Box::into_raw with this length. We have unique
+            // access, so every element that was written is marked ready, and is
+            // read out and dropped exactly once
+            unsafe {
+                let segments = Box::from_raw(ptr::slice_from_raw_parts_mut(segments, 1 << block));
+                for segment in segments.iter() {
+                    let segment = segment.load(Ordering::Relaxed);
+                    if segment.is_null() {
+                        continue;
+                    }
+                    let segment = Box::from_raw(segment);
+                    for offset in (0..SEGMENT_LEN).filter(|&offset| segment.is_ready(offset)) {
+                        drop(segment.chunk.take(offset));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use alloc::{sync::Arc, vec::Vec};
+    use phenotype_macro::Phenotype;
+    use std::thread;
+
+    #[derive(Phenotype, PartialEq, Debug)]
+    enum Event {
+        Id(u64),
+        Message(Arc<str>),
+        Heartbeat,
+    }
+
+    #[test]
+    fn locate_segments() {
+        assert_eq!(locate(0), (0, 0));
+        assert_eq!(locate(1), (1, 0));
+        assert_eq!(locate(2), (1, 1));
+        assert_eq!(locate(3), (2, 0));
+        // The last segment is the first in its block, so there's room to spare
+        assert_eq!(
+            locate(usize::MAX / SEGMENT_LEN),
+            (DIRECTORY_BLOCKS - SEGMENT_LEN.trailing_zeros() as usize, 0)
+        );
+    }
+
+    #[test]
+    fn push_and_get_across_segments() {
+        let pp = SegmentedPeapod::new();
+        for i in 0..5000 {
+            let event = match i % 3 {
+                0 => Event::Id(i),
+                1 => Event::Message(Arc::from("hello")),
+                _ => Event::Heartbeat,
+            };
+            assert_eq!(pp.push(event), i as usize);
+        }
+        assert_eq!(pp.len(), 5000);
+        assert_eq!(*pp.get(SEGMENT_LEN * 3).unwrap(), Event::Id(3072));
+        assert_eq!(*pp.get(4999).unwrap(), Event::Message(Arc::from("hello")));
+        assert_eq!(pp.tag(SEGMENT_LEN - 1), Some(0));
+        assert_eq!(pp.tag(SEGMENT_LEN), Some(1));
+        assert!(pp.get(5000).is_none());
+        assert_eq!(pp.iter().filter(|e| **e == Event::Heartbeat).count(), 1666);
+    }
+
+    #[test]
+    fn drops_everything() {
+        let message: Arc<str> = Arc::from("shared");
+        let pp: SegmentedPeapod<_> = (0..3000).map(|_| Event::Message(message.clone())).collect();
+        assert_eq!(Arc::strong_count(&message), 3001);
+        drop(pp);
+        assert_eq!(Arc::strong_count(&message), 1);
+    }
+
+    #[test]
+    fn panicking_push_doesnt_stall_publishing() {
+        #[derive(Phenotype, PartialEq, Debug)]
+        enum Level {
+            Info,
+            Custom(#[phenotype(bits = 3)] u8),
+        }
+
+        let pp = SegmentedPeapod::new();
+        pp.push(Level::Info);
+        thread::scope(|s| {
+            // 8 doesn't fit in 3 bits, so cleave panics
+            let bad = s.spawn(|| pp.push(Level::Custom(8)));
+            assert!(bad.join().is_err());
+        });
+        for i in 0..8 {
+            pp.push(Level::Custom(i));
+        }
+        assert_eq!(pp.len(), 9);
+        assert_eq!(*pp.get(8).unwrap(), Level::Custom(7));
+    }
+
+    #[test]
+    fn many_producers() {
+        const PRODUCERS: u64 = 4;
+        // Still enough to fill a few segments under Miri
+        const EACH: u64 = if cfg!(miri) { 600 } else { 10_000 };
+        let pp = Arc::new(SegmentedPeapod::new());
+        thread::scope(|s| {
+            for producer in 0..PRODUCERS {
+                let pp = Arc::clone(&pp);
+                s.spawn(move || {
+                    for i in 0..EACH {
+                        pp.push(Event::Id(producer * EACH + i));
+                    }
+                });
+            }
+            for _ in 0..2 {
+                let pp = &pp;
+                s.spawn(move || {
+                    let mut seen = 0;
+                    while seen < (PRODUCERS * EACH) as usize {
+                        // Everything that's been published must be readable
+                        let len = pp.len();
+                        assert!(len >= seen);
+                        assert!((seen..len).all(|i| matches!(*pp.get(i).unwrap(), Event::Id(_))));
+                        seen = len;
+                    }
+                });
+            }
+        });
+
+        // Every element gets published, and each producer's elements stay in
+        // the order they were pushed
+        assert_eq!(pp.len(), (PRODUCERS * EACH) as usize);
+        let ids: Vec<u64> = pp
+            .iter()
+            .map(|e| match *e {
+                Event::Id(i) => i,
+                _ => unreachable!(),
+            })
+            .collect();
+        for producer in 0..PRODUCERS {
+            let mine: Vec<_> = ids.iter().filter(|&&i| i / EACH == producer).collect();
+            assert_eq!(mine.len(), EACH as usize);
+            assert!(mine.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+}
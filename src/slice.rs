@@ -0,0 +1,681 @@
+use crate::{
+    tag_vec::{TagOrder, TagWord},
+    PeaRef,
+};
+use bitvec::prelude::*;
+use core::{
+    fmt::{self, Debug},
+    marker::PhantomData,
+    mem,
+    ops::{Bound, Range, RangeBounds},
+};
+use phenotype_internal::{Frozen, Phenotype};
+
+// Turns range bounds into a concrete range, panicking like slice indexing does
+// if it is out of bounds
+pub(crate) fn to_range<R>(range: R, len: usize) -> Range<usize>
+where
+    R: RangeBounds<usize>,
+{
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start
+            .checked_add(1)
+            .expect("attempted to index slice from after maximum usize"),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end
+            .checked_add(1)
+            .expect("attempted to index slice up to maximum usize"),
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+    assert!(
+        start <= end,
+        "slice index starts at {start} but ends at {end}"
+    );
+    assert!(
+        end <= len,
+        "range end index {end} out of range for slice of length {len}"
+    );
+    start..end
+}
+
+/// A borrowed view of a contiguous run of elements in a [`Peapod`](crate::Peapod),
+/// created with [`Peapod::slice`](crate::Peapod::slice).
+///
+/// Like a `&[T]`, it can be passed around to give access to part of a collection
+/// without copying it or taking ownership. Elements are accessed through
+/// [`PeaRef`]s, as they aren't stored as `T`s.
+pub struct PeaSlice<'a, T, W = usize, O = Lsb0>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    tags: &'a BitSlice<W, O>,
+    data: &'a [T::Value],
+    _marker: PhantomData<*const T>,
+}
+
+// # Safety
+// A `PeaSlice` is like a `&[T]`, it only hands out `PeaRef`s to its elements
+unsafe impl<T, W, O> Send for PeaSlice<'_, T, W, O>
+where
+    T: Phenotype + Sync,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+unsafe impl<T, W, O> Sync for PeaSlice<'_, T, W, O>
+where
+    T: Phenotype + Sync,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+
+impl<'a, T, W, O> PeaSlice<'a, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    // **Note**: tags must hold exactly one valid tag for each value in data
+    pub(crate) fn new(tags: &'a BitSlice<W, O>, data: &'a [T::Value]) -> Self {
+        debug_assert_eq!(tags.len(), data.len() * T::BITS);
+        Self {
+            tags,
+            data,
+            _marker: PhantomData,
+        }
+    }
+
+    // **Note**: index must be in range
+    fn get_tag(&self, index: usize) -> usize {
+        O::load_tag(&self.tags[index * T::BITS..(index + 1) * T::BITS])
+    }
+
+    /// Returns the number of elements in the slice.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns whether the slice is empty (it contains no elements).
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns a reference to the element at `index`, or `None` if it is out
+    /// of bounds.
+    pub fn get(&self, index: usize) -> Option<PeaRef<'a, T>> {
+        let value = self.data.get(index)?;
+        // # Safety
+        // The tag matches the data
+        unsafe { Some(PeaRef::new(self.get_tag(index), value)) }
+    }
+
+    /// Returns a reference to the first element, or `None` if the slice is empty.
+    pub fn first(&self) -> Option<PeaRef<'a, T>> {
+        self.get(0)
+    }
+
+    /// Returns a reference to the last element, or `None` if the slice is empty.
+    pub fn last(&self) -> Option<PeaRef<'a, T>> {
+        self.get(self.len().checked_sub(1)?)
+    }
+
+    /// Returns the tag of the element at `index`, or `None` if it is out
+    /// of bounds. Like [`Peapod::tag`](crate::Peapod::tag), this leaves out
+    /// any fields packed into the tag.
+    pub fn tag(&self, index: usize) -> Option<usize> {
+        (index < self.len()).then(|| T::variant(self.get_tag(index)))
+    }
+
+    /// Returns an iterator over the tags of the elements in the slice.
+    pub fn tags(&self) -> impl DoubleEndedIterator<Item = usize> + ExactSizeIterator + 'a {
+        // Not `chunks_exact`, which panics if `T::BITS` is 0
+        let tags = self.tags;
+        (0..self.len()).map(move |i| T::variant(O::load_tag(&tags[i * T::BITS..(i + 1) * T::BITS])))
+    }
+
+    /// Returns the number of elements in the slice that are the variant `tag`.
+    pub fn count_tag(&self, tag: usize) -> usize {
+        self.tags().filter(|&t| t == tag).count()
+    }
+
+    /// Returns whether any element in the slice is the variant `tag`.
+    pub fn contains_tag(&self, tag: usize) -> bool {
+        self.tags().any(|t| t == tag)
+    }
+
+    /// Returns the index of the first element that is the variant `tag`.
+    pub fn position_tag(&self, tag: usize) -> Option<usize> {
+        self.tags().position(|t| t == tag)
+    }
+
+    /// Returns an iterator over references to the elements of the slice.
+    pub fn iter(&self) -> Iter<'a, T, W, O> {
+        Iter { slice: *self }
+    }
+
+    /// Returns an iterator over the runs of consecutive elements that are the
+    /// same variant, as pairs of the tag and the range of indices the run covers.
+    pub fn group_runs(&self) -> GroupRuns<'a, T, W, O> {
+        GroupRuns {
+            slice: *self,
+            index: 0,
+        }
+    }
+
+    /// Returns a sub-slice covering `range`.
+    ///
+    /// ## Panics
+    /// Panics if `range` is out of bounds.
+    pub fn slice<R>(&self, range: R) -> Self
+    where
+        R: RangeBounds<usize>,
+    {
+        let Range { start, end } = to_range(range, self.len());
+        Self::new(
+            &self.tags[start * T::BITS..end * T::BITS],
+            &self.data[start..end],
+        )
+    }
+
+    /// Divides the slice in two at `mid`. The first contains the elements before
+    /// `mid`, and the second the elements from `mid` on.
+    ///
+    /// ## Panics
+    /// Panics if `mid > len`.
+    pub fn split_at(&self, mid: usize) -> (Self, Self) {
+        let (left, right) = self.data.split_at(mid);
+        let (left_tags, right_tags) = self.tags.split_at(mid * T::BITS);
+        (Self::new(left_tags, left), Self::new(right_tags, right))
+    }
+
+    /// Returns an iterator over non-overlapping sub-slices of `size` elements.
+    /// The last sub-slice will be shorter if `size` doesn't divide the length.
+    ///
+    /// ## Panics
+    /// Panics if `size` is 0.
+    pub fn chunks(&self, size: usize) -> Chunks<'a, T, W, O> {
+        assert!(size != 0, "chunk size must be non-zero");
+        Chunks { slice: *self, size }
+    }
+
+    /// Returns an iterator over all overlapping sub-slices of `size` elements.
+    ///
+    /// ## Panics
+    /// Panics if `size` is 0.
+    pub fn windows(&self, size: usize) -> Windows<'a, T, W, O> {
+        assert!(size != 0, "window size must be non-zero");
+        Windows { slice: *self, size }
+    }
+}
+
+impl<T, W, O> Clone for PeaSlice<'_, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, W, O> Copy for PeaSlice<'_, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+
+impl<T, W, O> Debug for PeaSlice<'_, T, W, O>
+where
+    T: Phenotype + Debug + Frozen,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T, W, O> IntoIterator for PeaSlice<'a, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Item = PeaRef<'a, T>;
+
+    type IntoIter = Iter<'a, T, W, O>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A mutable borrowed view of a contiguous run of elements in a
+/// [`Peapod`](crate::Peapod), created with [`Peapod::slice_mut`](crate::Peapod::slice_mut).
+///
+/// Elements can't be borrowed mutably as they aren't stored as `T`s, but they
+/// can be replaced, swapped and reordered.
+pub struct PeaSliceMut<'a, T, W = usize, O = Lsb0>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    tags: &'a mut BitSlice<W, O>,
+    data: &'a mut [T::Value],
+    _marker: PhantomData<*mut T>,
+}
+
+// # Safety
+// A `PeaSliceMut` is like a `&mut [T]`, it can move elements in and out
+unsafe impl<T, W, O> Send for PeaSliceMut<'_, T, W, O>
+where
+    T: Phenotype + Send,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+unsafe impl<T, W, O> Sync for PeaSliceMut<'_, T, W, O>
+where
+    T: Phenotype + Sync,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+
+impl<'a, T, W, O> PeaSliceMut<'a, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    // **Note**: tags must hold exactly one valid tag for each value in data
+    pub(crate) fn new(tags: &'a mut BitSlice<W, O>, data: &'a mut [T::Value]) -> Self {
+        debug_assert_eq!(tags.len(), data.len() * T::BITS);
+        Self {
+            tags,
+            data,
+            _marker: PhantomData,
+        }
+    }
+
+    // **Note**: index must be in range
+    fn get_tag(&self, index: usize) -> usize {
+        O::load_tag(&self.tags[index * T::BITS..(index + 1) * T::BITS])
+    }
+
+    // **Note**: index must be in range
+    fn set_tag(&mut self, index: usize, tag: usize) {
+        O::store_tag(&mut self.tags[index * T::BITS..(index + 1) * T::BITS], tag);
+    }
+
+    /// Returns an immutable view of the slice.
+    pub fn as_slice(&self) -> PeaSlice<'_, T, W, O> {
+        PeaSlice::new(self.tags, self.data)
+    }
+
+    /// Turns this into an immutable view, keeping the original lifetime.
+    pub fn into_slice(self) -> PeaSlice<'a, T, W, O> {
+        PeaSlice::new(self.tags, self.data)
+    }
+
+    /// Returns the number of elements in the slice.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns whether the slice is empty (it contains no elements).
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns a reference to the element at `index`, or `None` if it is out
+    /// of bounds.
+    pub fn get(&self, index: usize) -> Option<PeaRef<'_, T>> {
+        self.as_slice().get(index)
+    }
+
+    /// Returns an iterator over references to the elements of the slice.
+    pub fn iter(&self) -> Iter<'_, T, W, O> {
+        self.as_slice().iter()
+    }
+
+    /// Replaces the element at `index` with `t`, returning the old element.
+    ///
+    /// ## Panics
+    /// Panics if `index` is out of bounds.
+    pub fn replace(&mut self, index: usize, t: T) -> T {
+        let len = self.len();
+        assert!(index < len, "index (is {index}) should be < len (is {len})");
+
+        let old_tag = self.get_tag(index);
+        let (tag, data) = t.cleave();
+        let old = mem::replace(&mut self.data[index], data);
+        self.set_tag(index, tag);
+
+        // # Safety
+        // The tag matches the data
+        unsafe { T::reknit(old_tag, old) }
+    }
+
+    /// Swaps the elements at `a` and `b`.
+    ///
+    /// ## Panics
+    /// Panics if `a` or `b` are out of bounds.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.data.swap(a, b);
+        let (tag_a, tag_b) = (self.get_tag(a), self.get_tag(b));
+        self.set_tag(a, tag_b);
+        self.set_tag(b, tag_a);
+    }
+
+    /// Reverses the order of the elements in the slice.
+    pub fn reverse(&mut self) {
+        let len = self.len();
+        for i in 0..len / 2 {
+            self.swap(i, len - 1 - i);
+        }
+    }
+
+    /// Returns a mutable sub-slice covering `range`.
+    ///
+    /// ## Panics
+    /// Panics if `range` is out of bounds.
+    pub fn slice_mut<R>(&mut self, range: R) -> PeaSliceMut<'_, T, W, O>
+    where
+        R: RangeBounds<usize>,
+    {
+        let Range { start, end } = to_range(range, self.len());
+        PeaSliceMut::new(
+            &mut self.tags[start * T::BITS..end * T::BITS],
+            &mut self.data[start..end],
+        )
+    }
+}
+
+impl<T, W, O> Debug for PeaSliceMut<'_, T, W, O>
+where
+    T: Phenotype + Debug + Frozen,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.as_slice(), f)
+    }
+}
+
+/// An iterator over references to the elements of a [`PeaSlice`].
+pub struct Iter<'a, T, W = usize, O = Lsb0>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    slice: PeaSlice<'a, T, W, O>,
+}
+
+impl<'a, T, W, O> Iterator for Iter<'a, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Item = PeaRef<'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.slice.first()?;
+        self.slice = self.slice.slice(1..);
+        Some(first)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.slice.len(), Some(self.slice.len()))
+    }
+}
+
+impl<T, W, O> DoubleEndedIterator for Iter<'_, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let last = self.slice.last()?;
+        self.slice = self.slice.slice(..self.slice.len() - 1);
+        Some(last)
+    }
+}
+
+impl<T, W, O> ExactSizeIterator for Iter<'_, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+
+/// An iterator over non-overlapping sub-slices of a [`PeaSlice`], created with
+/// [`PeaSlice::chunks`].
+pub struct Chunks<'a, T, W = usize, O = Lsb0>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    slice: PeaSlice<'a, T, W, O>,
+    size: usize,
+}
+
+impl<'a, T, W, O> Iterator for Chunks<'a, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Item = PeaSlice<'a, T, W, O>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.slice.is_empty() {
+            return None;
+        }
+        let (chunk, rest) = self.slice.split_at(self.size.min(self.slice.len()));
+        self.slice = rest;
+        Some(chunk)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let chunks = self.slice.len().div_ceil(self.size);
+        (chunks, Some(chunks))
+    }
+}
+
+impl<T, W, O> ExactSizeIterator for Chunks<'_, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+
+/// An iterator over overlapping sub-slices of a [`PeaSlice`], created with
+/// [`PeaSlice::windows`].
+pub struct Windows<'a, T, W = usize, O = Lsb0>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    slice: PeaSlice<'a, T, W, O>,
+    size: usize,
+}
+
+impl<'a, T, W, O> Iterator for Windows<'a, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Item = PeaSlice<'a, T, W, O>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.slice.len() < self.size {
+            return None;
+        }
+        let window = self.slice.slice(..self.size);
+        self.slice = self.slice.slice(1..);
+        Some(window)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let windows = (self.slice.len() + 1).saturating_sub(self.size);
+        (windows, Some(windows))
+    }
+}
+
+impl<T, W, O> ExactSizeIterator for Windows<'_, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+
+/// An iterator over the runs of elements of the same variant in a [`PeaSlice`],
+/// created with [`PeaSlice::group_runs`].
+pub struct GroupRuns<'a, T, W = usize, O = Lsb0>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    slice: PeaSlice<'a, T, W, O>,
+    index: usize,
+}
+
+impl<T, W, O> Iterator for GroupRuns<'_, T, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Item = (usize, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.index;
+        let tag = self.slice.tag(start)?;
+        self.index += 1;
+        while self.slice.tag(self.index) == Some(tag) {
+            self.index += 1;
+        }
+        Some((tag, start..self.index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+    use super::*;
+    use crate::{peapod, Peapod};
+    use alloc::vec::Vec;
+    use phenotype_macro::Phenotype;
+
+    #[derive(Phenotype, PartialEq, Debug)]
+    enum Op {
+        Push(i64),
+        Add,
+        Mul,
+    }
+
+    fn program() -> Peapod<Op> {
+        peapod![
+            Op::Push(1),
+            Op::Push(2),
+            Op::Add,
+            Op::Push(3),
+            Op::Mul,
+            Op::Push(4),
+            Op::Add
+        ]
+    }
+
+    #[test]
+    fn slice_get_and_iter() {
+        let pp = program();
+        let slice = pp.slice(1..5);
+        assert_eq!(slice.len(), 4);
+        assert_eq!(*slice.get(0).unwrap(), Op::Push(2));
+        assert_eq!(*slice.last().unwrap(), Op::Mul);
+        assert!(slice.get(4).is_none());
+        assert_eq!(slice.tags().rev().collect::<Vec<_>>(), [2, 0, 1, 0]);
+        assert!(slice
+            .iter()
+            .rev()
+            .map(|op| *op == Op::Add)
+            .eq([false, false, true, false]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn slice_out_of_bounds() {
+        program().slice(3..8);
+    }
+
+    #[test]
+    fn split_chunks_windows() {
+        let pp = program();
+        let (left, right) = pp.as_slice().split_at(3);
+        assert_eq!(left.len(), 3);
+        assert_eq!(*right.first().unwrap(), Op::Push(3));
+
+        let chunks: Vec<_> = pp.as_slice().chunks(3).map(|c| c.len()).collect();
+        assert_eq!(chunks, [3, 3, 1]);
+
+        let windows = pp.as_slice().windows(2);
+        assert_eq!(windows.len(), 6);
+        let pushes_then_add = windows
+            .filter(|w| w.tag(0) == Some(0) && w.tag(1) == Some(1))
+            .count();
+        assert_eq!(pushes_then_add, 2);
+    }
+
+    #[test]
+    fn tag_queries() {
+        let pp = program();
+        let slice = pp.slice(2..);
+        assert_eq!(slice.count_tag(1), 2);
+        assert!(slice.contains_tag(2));
+        assert!(!slice.slice(..1).contains_tag(0));
+        assert_eq!(slice.position_tag(0), Some(1));
+    }
+
+    #[test]
+    fn slice_mut_replace_swap_reverse() {
+        let mut pp = program();
+        let mut slice = pp.slice_mut(..3);
+        assert_eq!(slice.replace(2, Op::Mul), Op::Add);
+        slice.swap(0, 1);
+        pp.slice_mut(3..).reverse();
+        assert_eq!(
+            Vec::from(pp),
+            [
+                Op::Push(2),
+                Op::Push(1),
+                Op::Mul,
+                Op::Add,
+                Op::Push(4),
+                Op::Mul,
+                Op::Push(3)
+            ]
+        );
+    }
+}
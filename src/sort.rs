@@ -0,0 +1,201 @@
+extern crate alloc;
+use crate::{
+    slice::GroupRuns,
+    tag_vec::{TagOrder, TagWord},
+    Peapod,
+};
+use alloc::{vec, vec::Vec};
+use allocator_api2::alloc::Allocator;
+use core::cmp::Ordering;
+use phenotype_internal::{Frozen, Phenotype};
+
+impl<T, A, W, O> Peapod<T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator + Clone,
+    W: TagWord,
+    O: TagOrder,
+{
+    /// Sorts the collection by variant, so all elements of the same variant end
+    /// up next to each other, in tag order. The sort is stable, so elements of the
+    /// same variant keep their relative order.
+    ///
+    /// This is a counting sort on the tags, so it runs in `O(n)` and never
+    /// reknits an element.
+    pub fn sort_by_tag(&mut self) {
+        let mut starts = vec![0; T::NUM_VARIANTS];
+        for i in 0..self.len() {
+            starts[self.get_variant(i)] += 1;
+        }
+        // Turn the counts into the index each variant starts at
+        let mut total = 0;
+        for start in &mut starts {
+            let count = *start;
+            *start = total;
+            total += count;
+        }
+
+        let mut order = vec![0; self.len()];
+        for i in 0..self.len() {
+            let variant = self.get_variant(i);
+            order[starts[variant]] = i;
+            starts[variant] += 1;
+        }
+        self.permute(&order);
+    }
+
+    /// Sorts the collection with a comparator function. The sort is stable.
+    ///
+    /// The comparator is passed references to reknit elements, see [`PeaRef`](crate::PeaRef).
+    /// If it panics, the collection is left as it was.
+    pub fn sort_by<F>(&mut self, mut compare: F)
+    where
+        T: Frozen,
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        let mut order: Vec<usize> = (0..self.len()).collect();
+        order.sort_by(|&a, &b| compare(&self.view(a), &self.view(b)));
+        self.permute(&order);
+    }
+
+    /// Sorts the collection with a key extraction function. The sort is stable.
+    ///
+    /// If `f` panics, the collection is left as it was.
+    pub fn sort_by_key<K, F>(&mut self, mut f: F)
+    where
+        T: Frozen,
+        F: FnMut(&T) -> K,
+        K: Ord,
+    {
+        self.sort_by(|a, b| f(a).cmp(&f(b)));
+    }
+
+    /// Sorts the collection. The sort is stable.
+    pub fn sort(&mut self)
+    where
+        T: Ord + Frozen,
+    {
+        self.sort_by(T::cmp);
+    }
+
+    /// Splits the collection into one collection per variant, indexed by tag.
+    /// The returned `Vec` always has `T::NUM_VARIANTS` collections, and each one
+    /// keeps the elements in their original order.
+    ///
+    /// Elements are moved without being reknit.
+    pub fn partition_by_tag(self) -> Vec<Peapod<T, A, W, O>> {
+        let mut pods: Vec<_> = (0..T::NUM_VARIANTS)
+            .map(|_| Peapod::empty_in(self.allocator().clone()))
+            .collect();
+        let (tags, data) = self.cleave();
+        for (i, value) in data.into_iter().enumerate() {
+            let tag = O::load_tag(&tags[i * T::BITS..(i + 1) * T::BITS]);
+            // # Safety
+            // We're moving the element over with its own tag
+            unsafe { pods[T::variant(tag)].push_cleaved(tag, value) };
+        }
+        pods
+    }
+
+    /// Returns an iterator over the runs of consecutive elements that are the
+    /// same variant, as pairs of the tag and the range of indices the run covers.
+    pub fn group_runs(&self) -> GroupRuns<'_, T, W, O> {
+        self.as_slice().group_runs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peapod;
+    use phenotype_macro::Phenotype;
+
+    #[derive(Phenotype, PartialEq, Eq, PartialOrd, Ord, Debug)]
+    enum Event {
+        Open(u32),
+        Close(u32),
+        Tick,
+    }
+
+    fn events() -> Peapod<Event> {
+        peapod![
+            Event::Tick,
+            Event::Close(1),
+            Event::Open(2),
+            Event::Tick,
+            Event::Open(1),
+            Event::Close(2),
+            Event::Open(3)
+        ]
+    }
+
+    #[test]
+    fn sort_by_tag_is_stable() {
+        let mut pp = events();
+        pp.sort_by_tag();
+        assert_eq!(
+            Vec::from(pp),
+            [
+                Event::Open(2),
+                Event::Open(1),
+                Event::Open(3),
+                Event::Close(1),
+                Event::Close(2),
+                Event::Tick,
+                Event::Tick
+            ]
+        );
+    }
+
+    #[test]
+    fn sort_and_sort_by() {
+        let mut pp = events();
+        pp.sort();
+        assert_eq!(
+            Vec::from(pp),
+            [
+                Event::Open(1),
+                Event::Open(2),
+                Event::Open(3),
+                Event::Close(1),
+                Event::Close(2),
+                Event::Tick,
+                Event::Tick
+            ]
+        );
+
+        let mut pp = events();
+        pp.sort_by_key(|event| match event {
+            Event::Open(n) | Event::Close(n) => *n,
+            Event::Tick => 0,
+        });
+        assert_eq!(pp.tag(0), Some(2));
+        assert_eq!(*pp.get(2).unwrap(), Event::Close(1));
+        assert_eq!(*pp.get(6).unwrap(), Event::Open(3));
+    }
+
+    #[test]
+    fn partition_by_tag_keeps_order() {
+        let pods = events().partition_by_tag();
+        assert_eq!(pods.len(), 3);
+        let mut pods = pods.into_iter().map(Vec::from);
+        assert_eq!(
+            pods.next().unwrap(),
+            [Event::Open(2), Event::Open(1), Event::Open(3)]
+        );
+        assert_eq!(pods.next().unwrap(), [Event::Close(1), Event::Close(2)]);
+        assert_eq!(pods.next().unwrap(), [Event::Tick, Event::Tick]);
+    }
+
+    #[test]
+    fn group_runs() {
+        let mut pp = events();
+        assert_eq!(pp.group_runs().count(), 7);
+        pp.sort_by_tag();
+        assert_eq!(
+            pp.group_runs().collect::<Vec<_>>(),
+            [(0, 0..3), (1, 3..5), (2, 5..7)]
+        );
+        assert_eq!(Peapod::<Event>::new().group_runs().next(), None);
+    }
+}
@@ -0,0 +1,561 @@
+extern crate alloc;
+use crate::{PeaRef, Peapod, TagOrder};
+use alloc::vec::{self, Vec};
+use bitvec::prelude::*;
+use core::{
+    fmt::{self, Debug},
+    marker::PhantomData,
+    mem::{ManuallyDrop, MaybeUninit},
+    ptr,
+};
+use phenotype_internal::{Frozen, Phenotype};
+
+// How many elements share one entry in the rank directory
+const RANK_BLOCK: usize = 512;
+
+/// Returns a `T::Value` that can be passed to `reknit` along with the tag of a
+/// unit variant.
+/// # Safety
+/// The result must only be `reknit`ed with the tag of a variant without data,
+/// i.e. one where `T::HAS_DATA` is `false`.
+#[allow(clippy::uninit_assumed_init)]
+pub(crate) unsafe fn unit_value<T: Phenotype>() -> T::Value {
+    // # Safety
+    // `T::Value` is a union with a `()` field for every unit variant, just like
+    // `MaybeUninit`, so it is allowed to be uninitialized. Unit variants never
+    // read their part of the union when they are reknit.
+    MaybeUninit::uninit().assume_init()
+}
+
+/// A `Peapod`-like collection for `enum`s that are mostly unit variants
+/// (like `Option::None`).
+///
+/// Every element costs `T::BITS + 1` bits for its tag and a "has payload" bit,
+/// but only elements whose variant carries data (see [`Phenotype::HAS_DATA`])
+/// store a `T::Value`. Payloads are found by counting the "has payload" bits
+/// before an element, which is sped up by a small directory of counts.
+pub struct SparsePeapod<T>
+where
+    T: Phenotype,
+{
+    tags: BitVec,
+    // One bit per element, set if the element has a payload in `data`
+    payloads: BitVec,
+    // The number of payloads before each block of RANK_BLOCK elements
+    ranks: Vec<usize>,
+    data: Vec<T::Value>,
+    // See Peapod
+    _marker: PhantomData<*const T>,
+}
+
+// # Safety
+// A `SparsePeapod` owns its elements like a `Vec<T>` does
+unsafe impl<T> Send for SparsePeapod<T> where T: Phenotype + Send {}
+unsafe impl<T> Sync for SparsePeapod<T> where T: Phenotype + Sync {}
+
+impl<T> SparsePeapod<T>
+where
+    T: Phenotype,
+{
+    /// Create a new `SparsePeapod` with 0 capacity and 0 length. This does not allocate.
+    pub fn new() -> Self {
+        Self {
+            tags: BitVec::new(),
+            payloads: BitVec::new(),
+            ranks: Vec::new(),
+            data: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    // **Note**: index must be in range
+    fn get_tag(&self, index: usize) -> usize {
+        Lsb0::load_tag(&self.tags[index * T::BITS..(index + 1) * T::BITS])
+    }
+
+    // The number of payloads stored before element `index`
+    // **Note**: index must be <= len
+    fn rank(&self, index: usize) -> usize {
+        let block = index / RANK_BLOCK;
+        match self.ranks.get(block) {
+            Some(rank) => rank + self.payloads[block * RANK_BLOCK..index].count_ones(),
+            // index == len and len is a multiple of RANK_BLOCK
+            None => self.data.len(),
+        }
+    }
+
+    /// Append a new element to the end of the collection.
+    ///
+    /// ## Panics
+    /// Panics in the same cases as [`Peapod::push`]. If it panics, the
+    /// collection is left unchanged.
+    pub fn push(&mut self, t: T) {
+        let len = self.len();
+
+        // Make sure none of the pushes below can panic, so the tags, payload bits
+        // and data can never get out of step.
+        // See https://github.com/fprasx/peapod/issues/2
+        self.tags.reserve(T::BITS);
+        self.payloads.reserve(1);
+        self.data.reserve(1);
+        if len.is_multiple_of(RANK_BLOCK) {
+            self.ranks.reserve(1);
+        }
+
+        let (tag, data) = t.cleave();
+        let has_data = T::HAS_DATA[T::variant(tag)];
+
+        if len.is_multiple_of(RANK_BLOCK) {
+            self.ranks.push(self.data.len());
+        }
+        self.tags.resize((len + 1) * T::BITS, false);
+        Lsb0::store_tag(&mut self.tags[len * T::BITS..(len + 1) * T::BITS], tag);
+        self.payloads.push(has_data);
+        // Unit variants don't hold anything in `data`, so there is nothing to drop
+        if has_data {
+            self.data.push(data);
+        }
+    }
+
+    /// Remove an element from the end of the collection.
+    /// Returns `None` if the collection is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        let len = self.len();
+
+        if len == 0 {
+            return None;
+        }
+
+        let tag = self.get_tag(len - 1);
+        self.tags.truncate((len - 1) * T::BITS);
+        if (len - 1).is_multiple_of(RANK_BLOCK) {
+            self.ranks.pop();
+        }
+
+        // This is safe as we checked that the length is not 0
+        let data = if self.payloads.pop().unwrap() {
+            // Every set payload bit has a matching value
+            self.data.pop().unwrap()
+        } else {
+            // # Safety
+            // The element has no payload, so it is a unit variant
+            unsafe { unit_value::<T>() }
+        };
+
+        // # Safety
+        // The tag matches the data
+        unsafe { Some(Phenotype::reknit(tag, data)) }
+    }
+
+    /// Returns a reference to the element at `index`, or `None` if it is out
+    /// of bounds.
+    pub fn get(&self, index: usize) -> Option<PeaRef<'_, T>> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let tag = self.get_tag(index);
+        // # Safety
+        // The tag matches the data, and elements without a payload are unit variants
+        unsafe {
+            Some(if self.payloads[index] {
+                PeaRef::new(tag, &self.data[self.rank(index)])
+            } else {
+                PeaRef::from_value(tag, unit_value::<T>())
+            })
+        }
+    }
+
+    /// Returns an iterator over references to the elements of the collection.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            pod: self,
+            index: 0,
+            data_index: 0,
+        }
+    }
+
+    /// Returns the number of elements in the collection.
+    pub fn len(&self) -> usize {
+        self.payloads.len()
+    }
+
+    /// Returns whether the collection is empty (it contains no elements).
+    pub fn is_empty(&self) -> bool {
+        self.payloads.is_empty()
+    }
+
+    /// Returns the number of elements that carry data, i.e. the number of
+    /// `T::Value`s actually stored.
+    pub fn payload_count(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Removes all elements from the collection.
+    /// **Note**: this does not affect its allocated capacity.
+    pub fn clear(&mut self) {
+        while self.pop().is_some() {}
+    }
+
+    fn cleave(self) -> (BitVec, BitVec, Vec<T::Value>) {
+        let levitating = ManuallyDrop::new(self);
+        unsafe {
+            // # Safety
+            // We are reading from a reference,
+            // we have wrapped self in ManuallyDrop to prevent a double-free.
+            // The rank directory is dropped here as it isn't needed anymore.
+            drop(ptr::read(&levitating.ranks));
+            (
+                ptr::read(&levitating.tags),
+                ptr::read(&levitating.payloads),
+                ptr::read(&levitating.data),
+            )
+        }
+    }
+}
+
+impl<T> Drop for SparsePeapod<T>
+where
+    T: Phenotype,
+{
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+impl<T> Default for SparsePeapod<T>
+where
+    T: Phenotype,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Debug for SparsePeapod<T>
+where
+    T: Phenotype + Debug + Frozen,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> Extend<T> for SparsePeapod<T>
+where
+    T: Phenotype,
+{
+    fn extend<A: IntoIterator<Item = T>>(&mut self, iter: A) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for SparsePeapod<T>
+where
+    T: Phenotype,
+{
+    fn from_iter<A: IntoIterator<Item = T>>(iter: A) -> Self {
+        let mut pp = Self::new();
+        pp.extend(iter);
+        pp
+    }
+}
+
+impl<T> From<Peapod<T>> for SparsePeapod<T>
+where
+    T: Phenotype,
+{
+    fn from(pp: Peapod<T>) -> Self {
+        pp.into_iter().collect()
+    }
+}
+
+impl<T> From<SparsePeapod<T>> for Peapod<T>
+where
+    T: Phenotype,
+{
+    fn from(pp: SparsePeapod<T>) -> Self {
+        pp.into_iter().collect()
+    }
+}
+
+/// An iterator over references to the elements of a [`SparsePeapod`].
+pub struct Iter<'a, T>
+where
+    T: Phenotype,
+{
+    pod: &'a SparsePeapod<T>,
+    index: usize,
+    // The index of the next payload in `pod.data`
+    data_index: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T>
+where
+    T: Phenotype,
+{
+    type Item = PeaRef<'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Are we done iterating?
+        if self.index == self.pod.len() {
+            return None;
+        }
+
+        let tag = self.pod.get_tag(self.index);
+        // # Safety
+        // The tag matches the data, and elements without a payload are unit variants
+        let elem = unsafe {
+            if self.pod.payloads[self.index] {
+                self.data_index += 1;
+                PeaRef::new(tag, &self.pod.data[self.data_index - 1])
+            } else {
+                PeaRef::from_value(tag, unit_value::<T>())
+            }
+        };
+        self.index += 1;
+        Some(elem)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.pod.len() - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> where T: Phenotype {}
+
+impl<'a, T> IntoIterator for &'a SparsePeapod<T>
+where
+    T: Phenotype,
+{
+    type Item = PeaRef<'a, T>;
+
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for SparsePeapod<T>
+where
+    T: Phenotype,
+{
+    type Item = T;
+
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let (tags, payloads, data) = self.cleave();
+        IntoIter {
+            tags,
+            payloads,
+            data: data.into_iter(),
+            front: 0,
+            back: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// An owning iterator over the elements of a [`SparsePeapod`].
+pub struct IntoIter<T>
+where
+    T: Phenotype,
+{
+    tags: BitVec,
+    payloads: BitVec,
+    data: vec::IntoIter<T::Value>,
+    // The next index to yield from the front
+    front: usize,
+    // How many elements have been yielded from the back
+    back: usize,
+    _marker: PhantomData<*const T>,
+}
+
+// # Safety
+// An `IntoIter` owns the elements that are left, like `SparsePeapod`
+unsafe impl<T> Send for IntoIter<T> where T: Phenotype + Send {}
+unsafe impl<T> Sync for IntoIter<T> where T: Phenotype + Sync {}
+
+impl<T> IntoIter<T>
+where
+    T: Phenotype,
+{
+    // # Safety
+    // `index` must not have been yielded yet, and the payloads of all elements
+    // between it and the end it is taken from must have been yielded
+    unsafe fn take(&mut self, index: usize, from_back: bool) -> T {
+        let tag = Lsb0::load_tag(&self.tags[index * T::BITS..(index + 1) * T::BITS]);
+        let data = if self.payloads[index] {
+            let next = if from_back {
+                self.data.next_back()
+            } else {
+                self.data.next()
+            };
+            // Every set payload bit has a matching value
+            next.unwrap()
+        } else {
+            unit_value::<T>()
+        };
+        T::reknit(tag, data)
+    }
+}
+
+impl<T> Iterator for IntoIter<T>
+where
+    T: Phenotype,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Are we done iterating?
+        if self.front + self.back == self.payloads.len() {
+            return None;
+        }
+
+        // # Safety
+        // Elements are taken from the front in order
+        let elem = unsafe { self.take(self.front, false) };
+        self.front += 1;
+        Some(elem)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.payloads.len() - self.front - self.back;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T>
+where
+    T: Phenotype,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        // Are we done iterating?
+        if self.front + self.back == self.payloads.len() {
+            return None;
+        }
+
+        self.back += 1;
+        // # Safety
+        // Elements are taken from the back in order
+        unsafe { Some(self.take(self.payloads.len() - self.back, true)) }
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> where T: Phenotype {}
+
+impl<T> Drop for IntoIter<T>
+where
+    T: Phenotype,
+{
+    fn drop(&mut self) {
+        for _ in self {}
+        // The remaining values in self.data are ManuallyDrop'd union fields,
+        // so they have all been dropped by the loop above
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+    use phenotype_macro::Phenotype;
+
+    #[derive(Phenotype, PartialEq, Debug)]
+    enum Cell {
+        Empty,
+        Full(Box<u64>),
+        Marked,
+    }
+
+    // Every 20th cell is full
+    fn cells(n: usize) -> Vec<Cell> {
+        (0..n)
+            .map(|i| match i % 20 {
+                0 => Cell::Full(Box::new(i as u64)),
+                7 => Cell::Marked,
+                _ => Cell::Empty,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn has_data_table() {
+        assert_eq!(Cell::HAS_DATA, &[false, true, false]);
+    }
+
+    #[test]
+    fn only_payloads_are_stored() {
+        let pp: SparsePeapod<Cell> = cells(2000).into_iter().collect();
+        assert_eq!(pp.len(), 2000);
+        assert_eq!(pp.payload_count(), 100);
+    }
+
+    #[test]
+    fn get_across_rank_blocks() {
+        let pp: SparsePeapod<Cell> = cells(2000).into_iter().collect();
+        for (i, cell) in cells(2000).iter().enumerate() {
+            assert_eq!(*pp.get(i).unwrap(), *cell);
+        }
+        assert!(pp.get(2000).is_none());
+        assert!(pp.iter().zip(cells(2000)).all(|(a, b)| *a == b));
+        assert_eq!(pp.iter().len(), 2000);
+    }
+
+    #[test]
+    fn push_pop() {
+        let mut pp = SparsePeapod::new();
+        assert_eq!(pp.pop(), None);
+        for cell in cells(RANK_BLOCK + 1) {
+            pp.push(cell);
+        }
+        let mut expected = cells(RANK_BLOCK + 1);
+        while let Some(cell) = pp.pop() {
+            assert_eq!(Some(cell), expected.pop());
+        }
+        assert!(pp.is_empty());
+        assert_eq!(pp.payload_count(), 0);
+    }
+
+    #[test]
+    fn into_iter_both_ends() {
+        let pp: SparsePeapod<Cell> = cells(41).into_iter().collect();
+        let mut iter = pp.into_iter();
+        assert_eq!(iter.next_back(), Some(Cell::Full(Box::new(40))));
+        assert_eq!(iter.next(), Some(Cell::Full(Box::new(0))));
+        assert_eq!(iter.len(), 39);
+        let rest: Vec<_> = iter.collect();
+        assert_eq!(rest, cells(40).into_iter().skip(1).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn peapod_round_trip() {
+        let pp = Peapod::from(cells(100));
+        let sparse = SparsePeapod::from(pp);
+        assert_eq!(Vec::from(Peapod::from(sparse)), cells(100));
+    }
+
+    #[test]
+    fn over_aligned_payloads() {
+        use crate::test_util::wide;
+
+        wide!();
+
+        let wide = |i: u64| match i % 5 {
+            0 => Wide::lanes(i),
+            _ => Wide::Empty,
+        };
+        let mut pp: SparsePeapod<Wide<u128>> = (0..20).map(wide).collect();
+        assert_eq!(pp.payload_count(), 4);
+        assert_eq!(*pp.get(15).unwrap(), wide(15));
+        assert_eq!(pp.pop(), Some(wide(19)));
+        let mut iter = pp.into_iter();
+        assert_eq!(iter.next(), Some(wide(0)));
+        assert_eq!(iter.next_back(), Some(wide(18)));
+    }
+}
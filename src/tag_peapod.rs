@@ -0,0 +1,782 @@
+extern crate alloc;
+
+use crate::{
+    sparse::unit_value,
+    tag_vec::{TagOrder, TagVec, TagWord},
+    MemoryUsage, PeaRef, Peapod,
+};
+use allocator_api2::{
+    alloc::{Allocator, Global},
+    vec::Vec as AllocVec,
+};
+use bitvec::prelude::*;
+use core::{
+    fmt::{self, Debug},
+    marker::PhantomData,
+    mem::{self, ManuallyDrop},
+    ptr,
+};
+use phenotype_internal::{Frozen, Phenotype};
+
+/// A `Peapod`-like collection for `enum`s whose variants carry no data, like
+/// cell states or flags (see [`Phenotype::ALL_UNIT`]).
+///
+/// A [`Peapod`] of such an enum still keeps a `Vec` of empty `T::Value`s to
+/// track its length. There's nothing in them, so this collection is nothing
+/// but the tags and a length: each element costs exactly `T::BITS` bits.
+/// Converting to and from a `Peapod` moves the tags over without copying them.
+///
+/// ```rust
+/// use peapod::{Phenotype, TagPeapod};
+///
+/// #[derive(Phenotype, PartialEq, Debug)]
+/// enum Cell {
+///     Dead,
+///     Alive,
+///     Dying,
+/// }
+///
+/// let mut cells: TagPeapod<Cell> = (0..1000)
+///     .map(|i| if i % 3 == 0 { Cell::Alive } else { Cell::Dead })
+///     .collect();
+/// cells.set(1, Cell::Dying);
+/// assert_eq!(*cells.get(1).unwrap(), Cell::Dying);
+/// // Two bits an element, and nothing else
+/// assert_eq!(cells.tag_bits().len(), 2000);
+/// assert_eq!(cells.memory_usage().data_bytes, 0);
+/// ```
+///
+/// `T::ALL_UNIT` is checked at compile time, so creating a `TagPeapod` of an
+/// enum with data doesn't compile:
+///
+/// ```compile_fail
+/// use peapod::{Phenotype, TagPeapod};
+///
+/// #[derive(Phenotype)]
+/// enum Slot {
+///     Empty,
+///     Full(u32),
+/// }
+///
+/// let slots = TagPeapod::<Slot>::new();
+/// ```
+pub struct TagPeapod<T: Phenotype, A: Allocator = Global, W: TagWord = usize, O: TagOrder = Lsb0> {
+    tags: TagVec<A, W, O>,
+    len: usize,
+    // See Peapod
+    _marker: PhantomData<*const T>,
+}
+
+// # Safety
+// A `TagPeapod` owns its elements like a `Peapod` does
+unsafe impl<T, A, W, O> Send for TagPeapod<T, A, W, O>
+where
+    T: Phenotype + Send,
+    A: Allocator + Send,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+
+unsafe impl<T, A, W, O> Sync for TagPeapod<T, A, W, O>
+where
+    T: Phenotype + Sync,
+    A: Allocator + Sync,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+
+impl<T> TagPeapod<T>
+where
+    T: Phenotype,
+{
+    /// Create a new `TagPeapod` with 0 capacity and 0 length. This does not
+    /// allocate.
+    pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    /// Creates a new collection with enough space to add `capacity` elements
+    /// without reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<T, A> TagPeapod<T, A>
+where
+    T: Phenotype,
+    A: Allocator,
+{
+    /// Creates a new `TagPeapod` with 0 capacity and 0 length, that will
+    /// allocate through `alloc`. This does not allocate.
+    pub fn new_in(alloc: A) -> Self {
+        Self::from_parts(TagVec::new_in(alloc), 0)
+    }
+
+    /// Creates a new collection with enough space to add `capacity` elements
+    /// without reallocating, that will allocate through `alloc`.
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        Self::from_parts(TagVec::with_capacity_in(capacity * T::BITS, alloc), 0)
+    }
+}
+
+impl<T, A, W, O> TagPeapod<T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    // **Note**: tags must hold exactly `len` valid tags
+    fn from_parts(tags: TagVec<A, W, O>, len: usize) -> Self {
+        const {
+            assert!(
+                T::ALL_UNIT,
+                "`TagPeapod` can only hold enums whose variants carry no data"
+            )
+        };
+        debug_assert_eq!(tags.len(), len * T::BITS);
+        Self {
+            tags,
+            len,
+            _marker: PhantomData,
+        }
+    }
+
+    // **Note**: index must be in range
+    fn get_tag(&self, index: usize) -> usize {
+        self.tags.load(index * T::BITS, T::BITS)
+    }
+
+    // **Note**: index must be in range
+    fn set_tag(&mut self, index: usize, tag: usize) {
+        self.tags.store(index * T::BITS, T::BITS, tag);
+    }
+
+    // # Safety
+    // `tag` must be a valid tag, which for an enum without data is all it takes
+    unsafe fn reknit(tag: usize) -> T {
+        T::reknit(tag, unit_value::<T>())
+    }
+
+    /// Append a new element to the end of the collection.
+    ///
+    /// ## Panics
+    /// Panics if the new capacity exceeds `isize::MAX` bytes.
+    pub fn push(&mut self, t: T) {
+        // There's no data, only the tag
+        let (tag, _) = t.cleave();
+        self.tags.push_bits(tag, T::BITS);
+        self.len += 1;
+    }
+
+    /// Remove an element from the end of the collection.
+    /// Returns `None` if the collection is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        let index = self.len.checked_sub(1)?;
+        let tag = self.get_tag(index);
+        self.tags.truncate(index * T::BITS);
+        self.len = index;
+        // # Safety
+        // The tag was stored by push
+        unsafe { Some(Self::reknit(tag)) }
+    }
+
+    /// Inserts an element at position `index`, shifting all elements after it
+    /// to the right.
+    ///
+    /// ## Panics
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, t: T) {
+        let len = self.len;
+        assert!(
+            index <= len,
+            "insertion index (is {index}) should be <= len (is {len})"
+        );
+        self.push(t);
+        // Shift the new tag from the end into place
+        self.tags[index * T::BITS..].rotate_right(T::BITS);
+    }
+
+    /// Removes and returns the element at position `index`, shifting all
+    /// elements after it to the left.
+    ///
+    /// ## Panics
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        let len = self.len;
+        assert!(
+            index < len,
+            "removal index (is {index}) should be < len (is {len})"
+        );
+        // Move the removed tag to the end, then pop it off
+        self.tags[index * T::BITS..].rotate_left(T::BITS);
+        self.pop().unwrap()
+    }
+
+    /// Replaces the element at `index` with `t`, returning the old one.
+    ///
+    /// ## Panics
+    /// Panics if `index` is out of bounds.
+    pub fn set(&mut self, index: usize, t: T) -> T {
+        let len = self.len;
+        assert!(index < len, "index (is {index}) should be < len (is {len})");
+        let old = self.get_tag(index);
+        let (tag, _) = t.cleave();
+        self.set_tag(index, tag);
+        // # Safety
+        // The tag was stored by push or set
+        unsafe { Self::reknit(old) }
+    }
+
+    /// Returns a reference to the element at `index`, or `None` if it is out
+    /// of bounds.
+    pub fn get(&self, index: usize) -> Option<PeaRef<'_, T>> {
+        // # Safety
+        // The tag was stored by push or set, and there's no data to own
+        (index < self.len)
+            .then(|| unsafe { PeaRef::from_value(self.get_tag(index), unit_value::<T>()) })
+    }
+
+    /// Returns the tag of the element at `index`, or `None` if it is out
+    /// of bounds. Like [`Peapod::tag`], this leaves out any fields packed into
+    /// the tag.
+    pub fn tag(&self, index: usize) -> Option<usize> {
+        (index < self.len).then(|| T::variant(self.get_tag(index)))
+    }
+
+    /// Returns the tags of all the elements, packed into `W`s in the order `O`,
+    /// `T::BITS` bits each. See [`Peapod::tag_bits`].
+    pub fn tag_bits(&self) -> &BitSlice<W, O> {
+        &self.tags
+    }
+
+    // See Peapod
+    pub(crate) fn tag_bits_mut(&mut self) -> &mut BitSlice<W, O> {
+        &mut self.tags
+    }
+
+    /// Returns the words the tags are packed into, see [`Peapod::tag_words`].
+    pub fn tag_words(&self) -> &[W] {
+        self.tags.words(self.tags.len())
+    }
+
+    /// Returns an iterator over references to the elements of the collection.
+    pub fn iter(&self) -> Iter<'_, T, A, W, O> {
+        Iter {
+            pod: self,
+            front: 0,
+            back: self.len,
+        }
+    }
+
+    /// Returns the number of elements in the collection.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the collection is empty (it contains no elements).
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of elements the collection can hold
+    /// without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.tags.capacity() / T::BITS
+    }
+
+    /// Reserves capacity for at least `additional` more elements.
+    ///
+    /// ## Panics
+    /// Panics if the new capacity exceeds `isize::MAX` bytes.
+    pub fn reserve(&mut self, additional: usize) {
+        self.tags.reserve(additional * T::BITS);
+    }
+
+    /// Shortens the collection to `len` elements, dropping the rest. Does
+    /// nothing if it's already shorter.
+    pub fn truncate(&mut self, len: usize) {
+        if mem::needs_drop::<T>() {
+            while self.len > len {
+                self.pop();
+            }
+        } else if len < self.len {
+            self.tags.truncate(len * T::BITS);
+            self.len = len;
+        }
+    }
+
+    /// Removes all elements from the collection.
+    /// **Note**: this does not affect its allocated capacity.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Shrinks the capacity of the collection as much as possible.
+    pub fn shrink_to_fit(&mut self) {
+        self.tags.shrink_to_fit();
+    }
+
+    /// Returns a breakdown of the memory the collection has allocated. There's
+    /// never any data.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let (tag_bytes, tag_capacity) = self.tags.bytes();
+        MemoryUsage {
+            tag_bytes,
+            data_bytes: 0,
+            spare_tag_bytes: tag_capacity - tag_bytes,
+            spare_data_bytes: 0,
+        }
+    }
+
+    fn cleave(self) -> (TagVec<A, W, O>, usize) {
+        let levitating = ManuallyDrop::new(self);
+        // # Safety
+        // We are reading from a reference,
+        // we have wrapped self in ManuallyDrop to prevent a double-free
+        unsafe { (ptr::read(&levitating.tags), levitating.len) }
+    }
+}
+
+impl<T, A, W, O> Drop for TagPeapod<T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn drop(&mut self) {
+        // Most enums without data have nothing to drop, so their tags can just
+        // be freed
+        if mem::needs_drop::<T>() {
+            while self.pop().is_some() {}
+        }
+    }
+}
+
+impl<T, W, O> Default for TagPeapod<T, Global, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn default() -> Self {
+        Self::from_parts(TagVec::new_in(Global), 0)
+    }
+}
+
+impl<T, A, W, O> Clone for TagPeapod<T, A, W, O>
+where
+    T: Phenotype + Clone,
+    A: Allocator + Clone,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn clone(&self) -> Self {
+        // Without data, an element is its tag, so copying the tags clones them
+        Self::from_parts(self.tags.clone(), self.len)
+    }
+}
+
+impl<T, A, W, O> PartialEq for TagPeapod<T, A, W, O>
+where
+    T: Phenotype + PartialEq + Frozen,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T, A, W, O> Eq for TagPeapod<T, A, W, O>
+where
+    T: Phenotype + Eq + Frozen,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+
+impl<T, A, W, O> Debug for TagPeapod<T, A, W, O>
+where
+    T: Phenotype + Debug + Frozen,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T, A, W, O> Extend<T> for TagPeapod<T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T, W, O> FromIterator<T> for TagPeapod<T, Global, W, O>
+where
+    T: Phenotype,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut pp = Self::default();
+        pp.extend(iter);
+        pp
+    }
+}
+
+impl<T, A, W, O> From<Peapod<T, A, W, O>> for TagPeapod<T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn from(pp: Peapod<T, A, W, O>) -> Self {
+        let len = pp.len();
+        // The data is all empty values, so there's nothing in it to keep
+        let (mut tags, _) = pp.cleave();
+        // Get rid of any cruft past the last tag
+        tags.truncate(len * T::BITS);
+        Self::from_parts(tags, len)
+    }
+}
+
+impl<T, A, W, O> From<TagPeapod<T, A, W, O>> for Peapod<T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator + Clone,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn from(pp: TagPeapod<T, A, W, O>) -> Self {
+        let (tags, len) = pp.cleave();
+        let mut data = AllocVec::with_capacity_in(len, tags.allocator().clone());
+        // # Safety
+        // Every variant is a unit variant. `T::Value` takes up no space, so
+        // this doesn't allocate
+        data.extend((0..len).map(|_| unsafe { unit_value::<T>() }));
+        Peapod::from_parts(tags, data)
+    }
+}
+
+impl<'a, T, A, W, O> IntoIterator for &'a TagPeapod<T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Item = PeaRef<'a, T>;
+
+    type IntoIter = Iter<'a, T, A, W, O>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, A, W, O> IntoIterator for TagPeapod<T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Item = T;
+
+    type IntoIter = IntoIter<T, A, W, O>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let back = self.len;
+        IntoIter {
+            pod: self,
+            front: 0,
+            back,
+        }
+    }
+}
+
+/// An iterator over references to the elements of a [`TagPeapod`].
+pub struct Iter<'a, T, A = Global, W = usize, O = Lsb0>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    pod: &'a TagPeapod<T, A, W, O>,
+    front: usize,
+    back: usize,
+}
+
+impl<'a, T, A, W, O> Iterator for Iter<'a, T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Item = PeaRef<'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        self.pod.get(self.front - 1)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T, A, W, O> DoubleEndedIterator for Iter<'_, T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        self.pod.get(self.back)
+    }
+}
+
+impl<T, A, W, O> ExactSizeIterator for Iter<'_, T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+
+/// An owning iterator over the elements of a [`TagPeapod`].
+pub struct IntoIter<T, A = Global, W = usize, O = Lsb0>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    // Elements outside `front..back` have been yielded. Dropping the collection
+    // drops everything left in it, so the yielded ones are cut off first
+    pod: TagPeapod<T, A, W, O>,
+    front: usize,
+    back: usize,
+}
+
+impl<T, A, W, O> Iterator for IntoIter<T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        // # Safety
+        // The tag was stored by push or set, and each one is only reknit once
+        unsafe {
+            Some(TagPeapod::<T, A, W, O>::reknit(
+                self.pod.get_tag(self.front - 1),
+            ))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T, A, W, O> DoubleEndedIterator for IntoIter<T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        // The element is yielded, so it's cut off
+        self.pod.pop()
+    }
+}
+
+impl<T, A, W, O> ExactSizeIterator for IntoIter<T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+}
+
+impl<T, A, W, O> Drop for IntoIter<T, A, W, O>
+where
+    T: Phenotype,
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn drop(&mut self) {
+        if mem::needs_drop::<T>() {
+            // Drop the elements that haven't been yielded, then forget the rest.
+            // The back ones have already been popped off
+            while self.pod.len > self.front {
+                self.pod.pop();
+            }
+            self.pod.len = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use phenotype_macro::Phenotype;
+
+    #[derive(Phenotype, PartialEq, Debug, Clone, Copy)]
+    enum Light {
+        Red,
+        Yellow,
+        Green,
+    }
+
+    fn lights() -> Vec<Light> {
+        (0..100)
+            .map(|i| match i % 3 {
+                0 => Light::Red,
+                1 => Light::Yellow,
+                _ => Light::Green,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn push_pop_insert_remove() {
+        let mut pp: TagPeapod<Light> = lights().into_iter().collect();
+        let mut expected = lights();
+        assert_eq!(pp.len(), 100);
+        assert_eq!(pp.tag_bits().len(), 200);
+
+        pp.insert(5, Light::Green);
+        expected.insert(5, Light::Green);
+        assert_eq!(pp.remove(50), expected.remove(50));
+        assert_eq!(pp.set(0, Light::Yellow), Light::Red);
+        expected[0] = Light::Yellow;
+        assert_eq!(pp.pop(), expected.pop());
+        assert_eq!(pp.tag(3), Some(0));
+        assert!(pp.iter().map(|light| *light).eq(expected.iter().copied()));
+        assert!(pp
+            .iter()
+            .rev()
+            .map(|light| *light)
+            .eq(expected.iter().rev().copied()));
+
+        pp.truncate(10);
+        assert_eq!(pp.len(), 10);
+        assert!(pp.capacity() >= 10);
+        let mut iter = pp.clone().into_iter();
+        assert_eq!(iter.next_back(), Some(expected[9]));
+        assert_eq!(iter.next(), Some(expected[0]));
+        assert_eq!(iter.len(), 8);
+        pp.clear();
+        assert!(pp.is_empty());
+    }
+
+    #[test]
+    fn peapod_round_trip() {
+        let mut pod: Peapod<Light, Global, u8, Msb0> = lights().into_iter().collect();
+        // Leave cruft past the last tag
+        pod.pop();
+        let tags = TagPeapod::from(pod);
+        assert_eq!(tags.len(), 99);
+        assert_eq!(tags.tag_words().len(), 25);
+        // Msb0 fills each byte from the top
+        assert_eq!(tags.tag_words()[0], 0b00_01_10_00);
+
+        let pod = Peapod::from(tags);
+        assert_eq!(pod.len(), 99);
+        assert_eq!(Vec::from(pod), lights()[..99]);
+    }
+
+    #[test]
+    fn drops_each_element_once() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Phenotype)]
+        enum Loud {
+            A,
+            B,
+        }
+        impl Drop for Loud {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let mut pp: TagPeapod<Loud> = (0..10).map(|_| Loud::A).collect();
+        pp.set(0, Loud::B);
+        assert_eq!(DROPS.swap(0, Ordering::Relaxed), 1);
+        drop(pp.remove(1));
+        pp.truncate(6);
+        assert_eq!(DROPS.swap(0, Ordering::Relaxed), 4);
+        let mut iter = pp.into_iter();
+        drop(iter.next());
+        drop(iter.next_back());
+        drop(iter);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 6);
+    }
+
+    #[test]
+    fn fields_packed_into_the_tag() {
+        #[derive(Phenotype, PartialEq, Debug)]
+        enum Level {
+            Info,
+            Custom(#[phenotype(bits = 3)] u8),
+        }
+
+        const { assert!(Level::ALL_UNIT) };
+        let pp: TagPeapod<Level> = [Level::Custom(6), Level::Info].into_iter().collect();
+        assert_eq!(pp.tag_words(), [1 | 6 << 1]);
+        assert_eq!(pp.tag(0), Some(1));
+        assert_eq!(*pp.get(0).unwrap(), Level::Custom(6));
+    }
+}
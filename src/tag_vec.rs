@@ -0,0 +1,381 @@
+use crate::PeapodError;
+use allocator_api2::{
+    alloc::{Allocator, Global},
+    vec::Vec,
+};
+use bitvec::prelude::*;
+use core::{
+    marker::PhantomData,
+    mem,
+    ops::{Deref, DerefMut},
+};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// An unsigned integer type that tags can be stored in: `u8`, `u16`, `u32`,
+/// `u64` or `usize`.
+///
+/// Tags are packed into a stream of these words, so with a fixed-size word and
+/// [`TagOrder`] the stream looks the same on every platform. See
+/// [`Peapod::tag_bits`](crate::Peapod::tag_bits).
+pub trait TagWord: BitStore + Copy + sealed::Sealed {
+    /// The number of bits in the word.
+    const BITS: usize;
+
+    // Reads the `bits` bits starting `shift` bits up from the least
+    // significant bit. `bits` must be between 1 and `Self::BITS`
+    #[doc(hidden)]
+    fn extract(self, shift: usize, bits: usize) -> usize;
+
+    // Overwrites the bits `extract` would read with `value`
+    #[doc(hidden)]
+    fn insert(self, shift: usize, bits: usize, value: usize) -> Self;
+}
+
+macro_rules! tag_word {
+    ($($word:ty),*) => {
+        $(
+            impl sealed::Sealed for $word {}
+
+            impl TagWord for $word {
+                const BITS: usize = <$word>::BITS as usize;
+
+                #[inline]
+                fn extract(self, shift: usize, bits: usize) -> usize {
+                    let mask = <$word>::MAX >> (<Self as TagWord>::BITS - bits);
+                    ((self >> shift) & mask) as usize
+                }
+
+                #[inline]
+                fn insert(self, shift: usize, bits: usize, value: usize) -> Self {
+                    let mask = <$word>::MAX >> (<Self as TagWord>::BITS - bits);
+                    (self & !(mask << shift)) | ((value as $word & mask) << shift)
+                }
+            }
+        )*
+    };
+}
+
+tag_word!(u8, u16, u32, u64, usize);
+
+/// The order tags are packed into each [`TagWord`]: [`Lsb0`] or [`Msb0`].
+///
+/// With `Lsb0`, tags fill each word from the least significant bit up, and a
+/// tag that spans two words keeps its low bits in the first. With `Msb0`, tags
+/// fill each word from the most significant bit down, and a tag that spans two
+/// words keeps its high bits in the first. Either way, the bits of a tag that
+/// fits in one word sit in the word in their usual significance.
+pub trait TagOrder: BitOrder + Sized + sealed::Sealed {
+    #[doc(hidden)]
+    fn load_tag<W: TagWord>(bits: &BitSlice<W, Self>) -> usize;
+
+    #[doc(hidden)]
+    fn store_tag<W: TagWord>(bits: &mut BitSlice<W, Self>, tag: usize);
+
+    // Adds the `index`th tag to a run of tags that will be stored all at once
+    #[doc(hidden)]
+    fn pack_tag(run: usize, index: usize, tag: usize, bits: usize) -> usize;
+
+    // How far up from the least significant bit a tag `offset` bits into its
+    // word sits
+    #[doc(hidden)]
+    fn shift(offset: usize, bits: usize, word: usize) -> usize;
+}
+
+impl sealed::Sealed for Lsb0 {}
+
+impl TagOrder for Lsb0 {
+    fn load_tag<W: TagWord>(bits: &BitSlice<W, Self>) -> usize {
+        // Enums stored whole have no tag bits, and bitvec can't load nothing
+        if bits.is_empty() {
+            return 0;
+        }
+        bits.load_le()
+    }
+
+    fn store_tag<W: TagWord>(bits: &mut BitSlice<W, Self>, tag: usize) {
+        if !bits.is_empty() {
+            bits.store_le(tag)
+        }
+    }
+
+    // Earlier tags are less significant
+    fn pack_tag(run: usize, index: usize, tag: usize, bits: usize) -> usize {
+        run | tag << (index * bits)
+    }
+
+    fn shift(offset: usize, _: usize, _: usize) -> usize {
+        offset
+    }
+}
+
+impl sealed::Sealed for Msb0 {}
+
+impl TagOrder for Msb0 {
+    fn load_tag<W: TagWord>(bits: &BitSlice<W, Self>) -> usize {
+        // Enums stored whole have no tag bits, and bitvec can't load nothing
+        if bits.is_empty() {
+            return 0;
+        }
+        bits.load_be()
+    }
+
+    fn store_tag<W: TagWord>(bits: &mut BitSlice<W, Self>, tag: usize) {
+        if !bits.is_empty() {
+            bits.store_be(tag)
+        }
+    }
+
+    // Earlier tags are more significant
+    fn pack_tag(run: usize, _: usize, tag: usize, bits: usize) -> usize {
+        run << bits | tag
+    }
+
+    fn shift(offset: usize, bits: usize, word: usize) -> usize {
+        word - offset - bits
+    }
+}
+
+// A growable bit vector, like `BitVec`, but allocated through `A`. bitvec has
+// no allocator support, so `Peapod` stores its tags in one of these.
+//
+// The bits live in `words`, and only the first `len` of them are in use. Bits
+// past `len` in the last word can be anything, so everything that grows the
+// vector writes the new bits explicitly.
+pub(crate) struct TagVec<A: Allocator = Global, W: TagWord = usize, O: TagOrder = Lsb0> {
+    words: Vec<W, A>,
+    len: usize,
+    _order: PhantomData<O>,
+}
+
+impl<A, W, O> TagVec<A, W, O>
+where
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    pub(crate) const fn new_in(alloc: A) -> Self {
+        Self {
+            words: Vec::new_in(alloc),
+            len: 0,
+            _order: PhantomData,
+        }
+    }
+
+    pub(crate) fn with_capacity_in(bits: usize, alloc: A) -> Self {
+        Self {
+            words: Vec::with_capacity_in(bits.div_ceil(W::BITS), alloc),
+            len: 0,
+            _order: PhantomData,
+        }
+    }
+
+    pub(crate) fn allocator(&self) -> &A {
+        self.words.allocator()
+    }
+
+    // The number of bits that fit without reallocating
+    pub(crate) fn capacity(&self) -> usize {
+        self.words
+            .capacity()
+            .saturating_mul(W::BITS)
+            .min(BitSlice::<W, O>::MAX_BITS)
+    }
+
+    pub(crate) fn push(&mut self, bit: bool) {
+        assert!(
+            self.len < BitSlice::<W, O>::MAX_BITS,
+            "tag capacity overflow"
+        );
+        if self.len == self.words.len() * W::BITS {
+            self.words.push(W::ZERO);
+        }
+        self.len += 1;
+        let last = self.len - 1;
+        self.set(last, bit);
+    }
+
+    // Appends `count` bits holding `bits`, stored like a tag, all at once rather
+    // than bit by bit
+    pub(crate) fn push_bits(&mut self, bits: usize, count: usize) {
+        debug_assert!(count <= usize::BITS as usize);
+        assert!(
+            self.len + count <= BitSlice::<W, O>::MAX_BITS,
+            "tag capacity overflow"
+        );
+        if count == 0 {
+            return;
+        }
+        let start = self.len;
+        let words = (start + count).div_ceil(W::BITS);
+        if words > self.words.len() {
+            self.words.resize(words, W::ZERO);
+        }
+        self.len += count;
+        // The bits past len can be anything, but storing overwrites all of them
+        O::store_tag(&mut self[start..], bits);
+    }
+
+    pub(crate) fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            self.len = len;
+            self.words.truncate(len.div_ceil(W::BITS));
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    pub(crate) fn resize(&mut self, len: usize, bit: bool) {
+        assert!(len <= BitSlice::<W, O>::MAX_BITS, "tag capacity overflow");
+        if len <= self.len {
+            self.truncate(len);
+            return;
+        }
+        let start = self.len;
+        self.words.resize(len.div_ceil(W::BITS), W::ZERO);
+        self.len = len;
+        self[start..].fill(bit);
+    }
+
+    pub(crate) fn extend_from_bitslice(&mut self, bits: &BitSlice<W, O>) {
+        let start = self.len;
+        self.resize(start + bits.len(), false);
+        self[start..].copy_from_bitslice(bits);
+    }
+
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        // Extending calls this once per word, so skip the math when we can
+        if additional <= self.capacity() - self.len {
+            return;
+        }
+        let words = self.words_needed(additional);
+        self.words.reserve(words);
+    }
+
+    pub(crate) fn reserve_exact(&mut self, additional: usize) {
+        let words = self.words_needed(additional);
+        self.words.reserve_exact(words);
+    }
+
+    pub(crate) fn try_reserve(&mut self, additional: usize) -> Result<(), PeapodError> {
+        let bits = self
+            .len
+            .checked_add(additional)
+            .filter(|&bits| bits <= BitSlice::<W, O>::MAX_BITS)
+            .ok_or(PeapodError::CapacityOverflow)?;
+        self.words
+            .try_reserve(bits.div_ceil(W::BITS) - self.words.len())?;
+        Ok(())
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.words.shrink_to_fit();
+    }
+
+    // Shrinks the capacity to at least `bits` bits, and never below the length
+    pub(crate) fn shrink_to(&mut self, bits: usize) {
+        self.words.shrink_to(bits.div_ceil(W::BITS));
+    }
+
+    // Loads the `bits` wide tag starting at bit `start`. A tag whose width
+    // divides the word size never spans two words, so it's read straight out
+    // of its word instead of going through bitvec
+    #[inline]
+    pub(crate) fn load(&self, start: usize, bits: usize) -> usize {
+        if bits != 0 && W::BITS % bits == 0 {
+            let offset = start % W::BITS;
+            self.words[start / W::BITS].extract(O::shift(offset, bits, W::BITS), bits)
+        } else {
+            O::load_tag(&self[start..start + bits])
+        }
+    }
+
+    // Stores a tag loaded by `load`
+    #[inline]
+    pub(crate) fn store(&mut self, start: usize, bits: usize, tag: usize) {
+        if bits != 0 && W::BITS % bits == 0 {
+            let offset = start % W::BITS;
+            let word = &mut self.words[start / W::BITS];
+            *word = word.insert(O::shift(offset, bits, W::BITS), bits, tag);
+        } else {
+            O::store_tag(&mut self[start..start + bits], tag);
+        }
+    }
+
+    // The words holding the first `bits` bits
+    pub(crate) fn words(&self, bits: usize) -> &[W] {
+        &self.words[..bits.div_ceil(W::BITS)]
+    }
+
+    // The bytes taken up by the words in use, and by the whole allocation
+    pub(crate) fn bytes(&self) -> (usize, usize) {
+        let word = mem::size_of::<W>();
+        (self.words.len() * word, self.words.capacity() * word)
+    }
+
+    // How many more words `additional` more bits need
+    fn words_needed(&self, additional: usize) -> usize {
+        let bits = self
+            .len
+            .checked_add(additional)
+            .expect("tag capacity overflow");
+        bits.div_ceil(W::BITS) - self.words.len()
+    }
+}
+
+impl<A, W, O> TagVec<A, W, O>
+where
+    A: Allocator + Clone,
+    W: TagWord,
+    O: TagOrder,
+{
+    pub(crate) fn split_off(&mut self, at: usize) -> Self {
+        let mut tail = Self::with_capacity_in(self.len - at, self.allocator().clone());
+        tail.extend_from_bitslice(&self[at..]);
+        self.truncate(at);
+        tail
+    }
+}
+
+impl<A, W, O> Deref for TagVec<A, W, O>
+where
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    type Target = BitSlice<W, O>;
+
+    fn deref(&self) -> &Self::Target {
+        &BitSlice::from_slice(&self.words)[..self.len]
+    }
+}
+
+impl<A, W, O> DerefMut for TagVec<A, W, O>
+where
+    A: Allocator,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut BitSlice::from_slice_mut(&mut self.words)[..self.len]
+    }
+}
+
+impl<A, W, O> Clone for TagVec<A, W, O>
+where
+    A: Allocator + Clone,
+    W: TagWord,
+    O: TagOrder,
+{
+    fn clone(&self) -> Self {
+        Self {
+            words: self.words.clone(),
+            len: self.len,
+            _order: PhantomData,
+        }
+    }
+}
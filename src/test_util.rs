@@ -0,0 +1,38 @@
+// Fixtures shared by the tests of the different collections
+
+use phenotype_macro::Frozen;
+
+// Packed data is never 32-byte aligned, so reading this out of place would be
+// caught by Miri
+#[repr(align(32))]
+#[derive(Frozen, PartialEq, Debug, Clone)]
+pub struct Simd(pub [u64; 4]);
+
+// Defines `Wide`, with over-aligned payloads next to unaligned ones, and ones
+// with drop glue so Miri catches anything that is leaked or dropped twice.
+// The types the derive generates are private to the module it's used in, so
+// each test module defines the enum itself.
+macro_rules! wide {
+    () => {
+        #[derive(::phenotype_macro::Phenotype, PartialEq, Debug, Clone)]
+        enum Wide<T> {
+            Int(u8, u128),
+            Simd {
+                lanes: $crate::test_util::Simd,
+                name: alloc::string::String,
+            },
+            Boxed(alloc::boxed::Box<$crate::test_util::Simd>, T),
+            Empty,
+        }
+
+        impl<T> Wide<T> {
+            fn lanes(i: u64) -> Self {
+                Wide::Simd {
+                    lanes: $crate::test_util::Simd([i; 4]),
+                    name: alloc::string::ToString::to_string(&i),
+                }
+            }
+        }
+    };
+}
+pub(crate) use wide;